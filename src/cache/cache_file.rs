@@ -0,0 +1,230 @@
+use crate::cache::cache_record::{IP_RECORD, SOA_RECORD};
+use crate::cache::{CacheRecord, IpCacheRecord, SoaCacheRecord, CacheMap};
+use crate::system::Result;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+//文件格式: 魔数(4) + 版本号(1) + n * (记录长度(4) + 校验和(4) + 记录)
+const FILE_MAGIC: &[u8; 4] = b"EDNS";
+const FILE_VERSION: u8 = 3;
+const HEADER_LEN: usize = 5;
+const RECORD_HEAD_LEN: usize = 8;
+
+pub struct CacheFile {
+    file_name: String,
+    //定时快照和退出时的写入可能同时发生, 同一时间只能有一个在写
+    lock: Mutex<()>,
+}
+
+impl CacheFile {
+    pub fn new(file_name: String) -> Self {
+        CacheFile {
+            file_name,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub async fn write(&self, map: &CacheMap) -> Result<()> {
+        if map.is_empty() {
+            info!("没有缓存需要写入文件");
+            return Ok(());
+        }
+        //和客户端网段相关的记录不写入文件, 文件里面只有域名没有网段
+        let bytes = encode(map.iter()
+            .filter(|e| e.key() == e.value().get_key())
+            .map(|e| e.value().to_bytes()));
+        let _guard = self.lock.lock().await;
+        write_atomic(&self.file_name, bytes.as_slice()).await?;
+        info!("缓存全部写入了文件! 文件名称是{}, 内存占用约{}字节", self.file_name, map.get_bytes());
+        Ok(())
+    }
+}
+
+fn encode(records: impl Iterator<Item=Vec<u8>>) -> Vec<u8> {
+    let mut vec = Vec::new();
+    vec.extend(FILE_MAGIC);
+    vec.push(FILE_VERSION);
+    records.for_each(|bytes| {
+        vec.extend(&(bytes.len() as u32).to_be_bytes());
+        vec.extend(&checksum(&bytes).to_be_bytes());
+        vec.extend(bytes);
+    });
+    vec
+}
+
+pub fn decode(bytes: &[u8]) -> Vec<CacheRecord> {
+    let mut records = Vec::new();
+    if bytes.len() < HEADER_LEN || &bytes[0..4] != FILE_MAGIC {
+        warn!("缓存文件没有合法的文件头, 忽略整个缓存文件");
+        return records;
+    }
+    if bytes[4] != FILE_VERSION {
+        warn!("不支持的缓存文件版本: {}, 忽略整个缓存文件", bytes[4]);
+        return records;
+    }
+    let mut index = HEADER_LEN;
+    while index + RECORD_HEAD_LEN <= bytes.len() {
+        let len = u32::from_be_bytes([bytes[index], bytes[index + 1],
+            bytes[index + 2], bytes[index + 3]]) as usize;
+        let sum = u32::from_be_bytes([bytes[index + 4], bytes[index + 5],
+            bytes[index + 6], bytes[index + 7]]);
+        index += RECORD_HEAD_LEN;
+        if len == 0 || index + len > bytes.len() {
+            warn!("缓存文件在第{}字节处被截断, 后面的记录会被忽略", index);
+            break;
+        }
+        let record_bytes = &bytes[index..index + len];
+        index += len;
+        if checksum(record_bytes) != sum {
+            warn!("缓存记录校验和不匹配, 跳过这条记录");
+            continue;
+        }
+        match record_bytes[0] {
+            IP_RECORD => records.push(IpCacheRecord::from(record_bytes).into()),
+            SOA_RECORD => records.push(SoaCacheRecord::from(record_bytes).into()),
+            flag => warn!("不支持的缓存记录类型: {}, 跳过这条记录", flag),
+        }
+    }
+    records
+}
+
+//先写临时文件再重命名, 保证进程在写的过程中被杀掉也不会留下写了一半的缓存文件
+async fn write_atomic(file_name: &str, bytes: &[u8]) -> Result<()> {
+    let tmp_name = format!("{}.tmp", file_name);
+    let mut file = File::create(&tmp_name).await?;
+    file.write_all(bytes).await?;
+    file.sync_all().await?;
+    drop(file);
+    tokio::fs::rename(&tmp_name, file_name).await?;
+    Ok(())
+}
+
+//crc32 (IEEE)
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::cache_file::{checksum, decode, encode, HEADER_LEN, RECORD_HEAD_LEN, CacheFile};
+    use crate::cache::{CacheItem, IpCacheRecord, SoaCacheRecord, CacheRecord};
+    use crate::cache::limit_map::LimitedMap;
+    use crate::system::Result;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn should_return_crc32_when_call_checksum_given_check_string() {
+        let result = checksum(b"123456789");
+
+        assert_eq!(0xCBF4_3926, result)
+    }
+
+    #[test]
+    fn should_return_same_records_when_decode_given_encoded_bytes() {
+        let records = vec![get_ip_record().to_bytes(), get_soa_record().to_bytes()];
+
+        let result = decode(&encode(records.clone().into_iter()));
+
+        let result: Vec<Vec<u8>> = result.iter().map(|r| r.to_bytes()).collect();
+        assert_eq!(records, result)
+    }
+
+    #[test]
+    fn should_keep_long_record_when_decode_given_record_over_255_bytes() {
+        let mut record = get_ip_record();
+        record.domain = "a".repeat(250) + ".com";
+        let bytes = record.to_bytes();
+        assert!(bytes.len() > 255);
+
+        let result = decode(&encode(vec![bytes.clone()].into_iter()));
+
+        assert_eq!(1, result.len());
+        assert_eq!(bytes, result[0].to_bytes())
+    }
+
+    #[test]
+    fn should_skip_record_when_decode_given_corrupt_record() {
+        let first = get_ip_record().to_bytes();
+        let mut file = encode(vec![first.clone(), get_soa_record().to_bytes()].into_iter());
+        file[HEADER_LEN + RECORD_HEAD_LEN + 3] ^= 0xFF;
+
+        let result = decode(&file);
+
+        assert_eq!(1, result.len());
+        assert_eq!(get_soa_record().to_bytes(), result[0].to_bytes())
+    }
+
+    #[test]
+    fn should_skip_record_when_decode_given_unknown_record_type() {
+        let unknown = vec![0u8, 1, 2, 3];
+
+        let result = decode(&encode(vec![unknown, get_soa_record().to_bytes()].into_iter()));
+
+        assert_eq!(1, result.len())
+    }
+
+    #[test]
+    fn should_return_complete_records_when_decode_given_truncated_file() {
+        let file = encode(vec![get_soa_record().to_bytes(), get_ip_record().to_bytes()].into_iter());
+
+        let result = decode(&file[0..file.len() - 1]);
+
+        assert_eq!(1, result.len())
+    }
+
+    #[test]
+    fn should_return_empty_when_decode_given_legacy_file() {
+        let mut legacy = get_ip_record().to_bytes();
+        legacy.insert(0, legacy.len() as u8);
+        legacy.push(0);
+
+        let result = decode(&legacy);
+
+        assert!(result.is_empty())
+    }
+
+    #[tokio::test]
+    async fn should_write_records_and_remove_tmp_file_when_call_write_given_not_empty_map() -> Result<()> {
+        let file_name = std::env::temp_dir().join("easydns_cache_file_test")
+            .to_str().unwrap().to_string();
+        let map = LimitedMap::from(10);
+        let record: CacheRecord = get_ip_record().into();
+        map.insert(record.get_key().clone(), record);
+        let file = CacheFile::new(file_name.clone());
+
+        file.write(&map).await?;
+
+        let result = decode(&tokio::fs::read(&file_name).await?);
+        assert_eq!(1, result.len());
+        assert!(tokio::fs::metadata(format!("{}.tmp", file_name)).await.is_err());
+        tokio::fs::remove_file(&file_name).await?;
+        Ok(())
+    }
+
+    fn get_ip_record() -> IpCacheRecord {
+        IpCacheRecord {
+            domain: "www.baidu.com".to_string(),
+            address: Ipv4Addr::from([1, 1, 1, 1]),
+            cnames: vec![],
+            create_time: 0,
+            ttl_ms: 1000,
+        }
+    }
+
+    fn get_soa_record() -> SoaCacheRecord {
+        SoaCacheRecord {
+            domain: "www.baidu.com".to_string(),
+            create_time: 0,
+            ttl_ms: 1000,
+        }
+    }
+}
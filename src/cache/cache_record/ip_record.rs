@@ -1,13 +1,15 @@
 use crate::system::{get_now};
 use crate::cache::cache_record::{CacheItem, IP_RECORD};
 use crate::cursor::Cursor;
-use crate::protocol::{DnsAnswer, Ipv4Answer};
+use crate::protocol::{Answer, DnsAnswer, Ipv4Answer};
 use std::net::Ipv4Addr;
 
 #[derive(Clone, PartialOrd, PartialEq, Debug)]
 pub struct IpCacheRecord {
     pub domain: String,
     pub address: Ipv4Addr,
+    //应答里面cname链上的域名, 从缓存取出来的时候也要检查是否被拦截
+    pub cnames: Vec<String>,
     pub create_time: u128,
     pub ttl_ms: u128,
 }
@@ -25,6 +27,12 @@ impl CacheItem for IpCacheRecord {
         &self.domain
     }
 
+    fn get_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.domain.capacity()
+            + self.cnames.iter().map(|e| std::mem::size_of::<String>() + e.capacity())
+            .sum::<usize>()
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         self.into()
     }
@@ -52,6 +60,16 @@ impl From<&IpCacheRecord> for Vec<u8> {
         vec.extend(&record.create_time.to_be_bytes());
         vec.push(4);
         vec.extend(&record.address.octets());
+        //数量和长度都只有一个字节, 超长的域名不写, 最多写255个
+        let cnames: Vec<&String> = record.cnames.iter()
+            .filter(|cname| cname.len() <= u8::MAX as usize)
+            .take(u8::MAX as usize)
+            .collect();
+        vec.push(cnames.len() as u8);
+        cnames.iter().for_each(|cname| {
+            vec.push(cname.len() as u8);
+            vec.extend(cname.as_bytes());
+        });
         vec
     }
 }
@@ -68,9 +86,16 @@ impl From<&[u8]> for IpCacheRecord {
         let create_time = u128::from_be_bytes(cursor.take_bytes());
         cursor.take();
         let address = Ipv4Addr::from(cursor.take_bytes());
+        //旧版本的记录没有cname部分
+        let count = if cursor.get_current_index() < bytes.len() { cursor.take() } else { 0 };
+        let cnames = (0..count).map(|_| {
+            let len = cursor.take() as usize;
+            String::from_utf8(Vec::from(cursor.take_slice(len))).unwrap()
+        }).collect();
         IpCacheRecord {
             domain,
             address,
+            cnames,
             create_time,
             ttl_ms,
         }
@@ -82,6 +107,7 @@ impl From<&Ipv4Answer> for IpCacheRecord {
         IpCacheRecord {
             domain: answer.get_name().clone(),
             address: answer.get_address().clone(),
+            cnames: answer.get_cnames().to_vec(),
             create_time: get_now(),
             ttl_ms: answer.get_ttl() as u128 * 1000,
         }
@@ -117,6 +143,42 @@ pub mod tests {
         assert_eq!(expected, result)
     }
 
+    #[test]
+    fn should_keep_cnames_when_from_bytes_given_record_bytes_with_cnames() {
+        TIME.with(|t| {
+            t.borrow_mut().set_timestamp(0);
+        });
+        let mut record = get_ip_record();
+        record.cnames = vec!["b.com".into(), "c.tracker.com".into()];
+
+        let result = IpCacheRecord::from(record.to_bytes().as_slice());
+
+        assert_eq!(record, result)
+    }
+
+    #[test]
+    fn should_return_empty_cnames_when_from_bytes_given_record_bytes_without_cnames() {
+        let mut vec = get_test_bytes();
+        vec.pop();
+
+        let result = IpCacheRecord::from(vec.as_slice());
+
+        assert!(result.cnames.is_empty())
+    }
+
+    #[test]
+    fn should_skip_long_cnames_when_to_bytes_given_record_with_long_cname() {
+        TIME.with(|t| {
+            t.borrow_mut().set_timestamp(0);
+        });
+        let mut record = get_ip_record();
+        record.cnames = vec!["a".repeat(300), "b.com".into()];
+
+        let result = IpCacheRecord::from(record.to_bytes().as_slice());
+
+        assert_eq!(vec!["b.com".to_string()], result.cnames)
+    }
+
     #[test]
     fn should_return_valid_record_when_from_answer_given_valid_answer() {
         let answer = get_ip_answer();
@@ -131,8 +193,8 @@ pub mod tests {
     }
 
     fn get_test_bytes() -> Vec<u8> {
-        let bytes: [u8; 44] = [42, 15, 3, 119, 119, 119, 5, 98, 97, 105, 100, 117, 3, 99, 111, 109, 0, 4, 0, 0, 3, 232, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 1, 1, 1, 1];
-        let mut vec = Vec::with_capacity(44);
+        let bytes: [u8; 45] = [42, 15, 3, 119, 119, 119, 5, 98, 97, 105, 100, 117, 3, 99, 111, 109, 0, 4, 0, 0, 3, 232, 16, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 1, 1, 1, 1, 0];
+        let mut vec = Vec::with_capacity(45);
         for c in bytes.iter() {
             vec.push(c.clone())
         }
@@ -143,6 +205,7 @@ pub mod tests {
         IpCacheRecord {
             domain: "www.baidu.com".to_string(),
             address: Ipv4Addr::from([1, 1, 1, 1]),
+            cnames: vec![],
             create_time: 0,
             ttl_ms: 1000,
         }
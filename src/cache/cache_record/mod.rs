@@ -2,7 +2,7 @@ mod ip_record;
 mod soa_record;
 
 use crate::system::get_now;
-use crate::cache::limit_map::GetOrdKey;
+use crate::cache::limit_map::{GetOrdKey, GetSize};
 
 pub use ip_record::IpCacheRecord;
 pub use soa_record::SoaCacheRecord;
@@ -37,6 +37,7 @@ pub trait CacheItem: Sync + Send + BoxedClone {
     fn get_create_time(&self) -> u128;
     fn get_ttl_ms(&self) -> u128;
     fn get_key(&self) -> &String;
+    fn get_size(&self) -> usize;
     fn to_bytes(&self) -> Vec<u8>;
     fn to_answer(&self) -> DnsAnswer;
 }
@@ -64,6 +65,15 @@ impl GetOrdKey for CacheRecord {
     }
 }
 
+impl GetSize for CacheRecord {
+    //map里面的key是domain的拷贝，所以也算进去
+    fn get_size(&self) -> usize {
+        let key = self.get_key();
+        std::mem::size_of::<CacheRecord>() + std::mem::size_of_val(key) + key.len()
+            + CacheItem::get_size(self.as_ref())
+    }
+}
+
 impl Debug for CacheRecord {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_tuple("")
@@ -179,6 +189,10 @@ pub mod tests {
             &self.key
         }
 
+        fn get_size(&self) -> usize {
+            std::mem::size_of::<Self>()
+        }
+
         fn to_bytes(&self) -> Vec<u8> {
             vec![]
         }
@@ -23,6 +23,10 @@ impl CacheItem for SoaCacheRecord {
         &self.domain
     }
 
+    fn get_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.domain.capacity()
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         self.into()
     }
@@ -1,4 +1,4 @@
-use crate::cache::{CacheStrategy, CacheMap, AnswerFuture};
+use crate::cache::{CacheStrategy, CacheMap, AnswerFuture, cache_key};
 use std::sync::Arc;
 use crate::system::get_now;
 use crate::system::Result;
@@ -16,7 +16,7 @@ impl CacheStrategy for ExpiredCacheStrategy {
         if record.is_expired(get_now()) {
             let answer = future.await?;
             if let Some(r) = answer.to_cache() {
-                self.map.insert(record.get_key().clone(), r);
+                self.map.insert(cache_key(record.get_key().clone(), &answer), r);
             }
             Ok(answer)
         } else {
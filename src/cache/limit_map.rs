@@ -8,18 +8,31 @@ pub trait GetOrdKey {
     fn get_order_key(&self) -> Self::Output;
 }
 
+pub trait GetSize {
+    //近似的内存占用，单位是字节
+    fn get_size(&self) -> usize;
+}
+
 pub struct LimitedMap<K, V> {
     records: DashMap<K, V>,
     limit: usize,
+    max_bytes: usize,
+    //锁里面存的是当前占用的字节数
     lock_key: Mutex<usize>,
 }
 
 impl<K, V> LimitedMap<K, V>
-    where K: Eq + Hash + Clone, V: Clone + GetOrdKey {
+    where K: Eq + Hash + Clone, V: Clone + GetOrdKey + GetSize {
     pub fn from(limit: usize) -> Self {
+        Self::with_max_bytes(limit, 0)
+    }
+
+    //max_bytes等于0代表不限制内存占用
+    pub fn with_max_bytes(limit: usize, max_bytes: usize) -> Self {
         LimitedMap {
             records: DashMap::with_capacity(limit),
             limit,
+            max_bytes,
             lock_key: Mutex::new(0),
         }
     }
@@ -29,21 +42,49 @@ impl<K, V> LimitedMap<K, V>
 
     pub fn insert(&self, key: K, value: V) {
         //如果超过了限制的大小，则删除掉十分之一最小的记录
-        let guard = self.lock_key.lock().unwrap();
+        let mut bytes = self.lock_key.lock().unwrap();
         if self.records.len() >= self.limit {
-            let vec = &mut Vec::new();
-            self.records.iter().for_each(|e| {
-                vec.push((e.key().clone(), e.value().get_order_key()))
-            });
-            vec.sort_unstable_by_key(|(_, sort_key)| sort_key.clone());
+            let vec = self.sorted_keys();
             let keys: Vec<&K> = vec[0..self.limit / 10].iter()
                 .map(|(k, _)| k).collect();
-            self.records.retain(|r, _| {
-                !keys.contains(&r)
+            self.records.retain(|r, v| {
+                if keys.contains(&r) {
+                    *bytes = bytes.saturating_sub(v.get_size());
+                    false
+                } else {
+                    true
+                }
             })
         }
-        drop(guard);
-        self.records.insert(key, value);
+        //如果超过了内存预算，则从最小的记录开始删除直到能放下新的记录
+        let size = value.get_size();
+        if self.max_bytes > 0 && *bytes + size > self.max_bytes {
+            for (k, _) in self.sorted_keys() {
+                if *bytes + size <= self.max_bytes {
+                    break;
+                }
+                if let Some((_, v)) = self.records.remove(&k) {
+                    *bytes = bytes.saturating_sub(v.get_size());
+                }
+            }
+        }
+        if let Some(old) = self.records.insert(key, value) {
+            *bytes = bytes.saturating_sub(old.get_size());
+        }
+        *bytes += size;
+    }
+
+    fn sorted_keys(&self) -> Vec<(K, V::Output)> {
+        let mut vec = Vec::with_capacity(self.records.len());
+        self.records.iter().for_each(|e| {
+            vec.push((e.key().clone(), e.value().get_order_key()))
+        });
+        vec.sort_unstable_by_key(|(_, sort_key)| sort_key.clone());
+        vec
+    }
+
+    pub fn get_bytes(&self) -> usize {
+        *self.lock_key.lock().unwrap()
     }
 
     pub fn iter(&self) -> dashmap::iter::Iter<K, V, RandomState, DashMap<K, V, RandomState>> {
@@ -57,7 +98,7 @@ impl<K, V> LimitedMap<K, V>
 
 #[cfg(test)]
 mod tests {
-    use crate::cache::limit_map::{LimitedMap, GetOrdKey};
+    use crate::cache::limit_map::{LimitedMap, GetOrdKey, GetSize};
 
     impl GetOrdKey for i32 {
         type Output = i32;
@@ -67,6 +108,12 @@ mod tests {
         }
     }
 
+    impl GetSize for i32 {
+        fn get_size(&self) -> usize {
+            4
+        }
+    }
+
     #[test]
     fn should_insert_into_map_when_call_insert_given_empty_map() {
         let map = LimitedMap::from(1);
@@ -95,6 +142,31 @@ mod tests {
         assert_eq!(None, over_result)
     }
 
+    #[test]
+    fn should_remove_min_records_when_call_insert_given_over_max_bytes() {
+        let map = LimitedMap::with_max_bytes(100, 12);
+        (0..3).for_each(|r| {
+            map.insert(r, r);
+        });
+
+        map.insert(1000, 1000);
+
+        assert_eq!(None, map.get(&0));
+        assert_eq!(Some(1000), map.get(&1000));
+        assert_eq!(3, map.records.len());
+        assert_eq!(12, map.get_bytes());
+    }
+
+    #[test]
+    fn should_not_count_twice_when_call_insert_given_same_key() {
+        let map = LimitedMap::with_max_bytes(100, 12);
+
+        map.insert(1, 1);
+        map.insert(1, 2);
+
+        assert_eq!(4, map.get_bytes());
+    }
+
     #[test]
     fn should_return_true_when_call_is_empty_given_empty_map() {
         let map = LimitedMap::<i32, i32>::from(1);
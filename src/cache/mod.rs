@@ -2,13 +2,15 @@ mod limit_map;
 mod expired_strategy;
 mod timeout_strategy;
 mod cache_record;
+mod cache_file;
+mod warm_up;
 
 use crate::config::Config;
-use crate::system::{Result, get_now, block_on};
+use crate::system::{Result, get_now};
 use std::sync::Arc;
 use limit_map::{LimitedMap};
 use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncReadExt;
 
 pub use cache_record::CacheRecord;
 pub use cache_record::IpCacheRecord;
@@ -16,16 +18,21 @@ pub use cache_record::SoaCacheRecord;
 pub use cache_record::CacheItem;
 use crate::cache::expired_strategy::ExpiredCacheStrategy;
 use crate::cache::timeout_strategy::TimeoutCacheStrategy;
-use crate::cache::cache_record::{IP_RECORD, Expired};
-use crate::cache::cache_record::SOA_RECORD;
-use crate::cursor::Cursor;
+use crate::cache::cache_record::Expired;
+use crate::cache::cache_file::CacheFile;
+use std::time::Duration;
+use tokio::time::interval;
 use async_trait::async_trait;
 use futures_util::future::BoxFuture;
-use crate::protocol::DnsAnswer;
+use futures_util::StreamExt;
+use futures_util::stream;
+use crate::protocol::{DnsAnswer, ClientSubnet};
+
+pub use warm_up::read_warm_up_domains;
 
 pub type CacheMap = LimitedMap<String, CacheRecord>;
 type ExpiredStrategy = Box<dyn CacheStrategy>;
-type AnswerFuture = BoxFuture<'static, Result<DnsAnswer>>;
+pub type AnswerFuture = BoxFuture<'static, Result<DnsAnswer>>;
 
 #[async_trait]
 pub trait CacheStrategy: Send + Sync {
@@ -34,23 +41,10 @@ pub trait CacheStrategy: Send + Sync {
 
 pub struct CachePool {
     strategy: ExpiredStrategy,
-    file_name: String,
+    file: Arc<CacheFile>,
     map: Arc<CacheMap>,
 }
 
-impl Drop for CachePool {
-    fn drop(&mut self) {
-        block_on(async move {
-            match self.write_to_file().await {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("把缓存写入文件出错: {:?}", e)
-                }
-            }
-        });
-    }
-}
-
 impl CachePool {
     pub async fn from(config: &Config) -> Result<Self> {
         let limit_map: Arc<CacheMap> = Arc::new(create_map_by_config(config).await?);
@@ -60,15 +54,24 @@ impl CachePool {
             Box::new(TimeoutCacheStrategy::from(limit_map.clone(),
                                                 config.cache_ttl_timeout_ms as u128))
         };
+        let file = Arc::new(CacheFile::new(config.cache_file.clone()));
+        if config.cache_snapshot_duration_m > 0 {
+            start_snapshot_task(file.clone(), limit_map.clone(),
+                                Duration::from_secs(config.cache_snapshot_duration_m as u64 * 60));
+        }
         Ok(CachePool {
             strategy,
-            file_name: config.cache_file.clone(),
+            file,
             map: limit_map,
         })
     }
-    pub async fn get(&self, key: String, future: AnswerFuture) -> Result<DnsAnswer> {
-        //从缓存map中取
-        match self.map.get(&key) {
+    pub async fn get(&self, key: String, subnet: Option<&ClientSubnet>,
+                     future: AnswerFuture) -> Result<DnsAnswer> {
+        //先取客户端所在网段的记录, 上游的scope可能比客户端的网段短, 从长到短都找一下, 没有再取所有网段共用的记录
+        let record = subnet.and_then(|s| {
+            (1..=s.source_prefix).rev().find_map(|prefix| self.map.get(&subnet_key(&key, s, prefix)))
+        }).or_else(|| self.map.get(&key));
+        match record {
             //缓存中有
             Some(r) => {
                 Ok(self.strategy.handle(r, future).await?)
@@ -77,77 +80,192 @@ impl CachePool {
             None => {
                 let answer = future.await?;
                 if let Some(r) = answer.to_cache() {
-                    self.map.insert(key, r);
+                    self.map.insert(cache_key(key, &answer), r);
                 }
                 Ok(answer)
             }
         }
     }
 
-    fn to_file_bytes(&self) -> Vec<u8> {
-        let mut vec = Vec::new();
-        self.map.iter().for_each(|e| {
-            let bytes = e.value().to_bytes();
-            vec.push(bytes.len() as u8);
-            vec.extend(bytes);
-        });
-        vec.push(0);
-        vec
+    //并发数最多为concurrency, 已经在缓存中的域名会跳过
+    pub async fn warm_up<F>(&self, domains: Vec<String>, concurrency: usize, resolve: F)
+        where F: Fn(String) -> AnswerFuture {
+        let total = domains.len();
+        stream::iter(domains).for_each_concurrent(concurrency.max(1), |domain| {
+            let future = if self.map.get(&domain).is_some() {
+                None
+            } else {
+                Some(resolve(domain.clone()))
+            };
+            async move {
+                if let Some(future) = future {
+                    match future.await {
+                        Ok(answer) => {
+                            if let Some(r) = answer.to_cache() {
+                                self.map.insert(cache_key(domain, &answer), r);
+                            }
+                        }
+                        Err(e) => {
+                            debug!("缓存预热解析{}出错: {:?}", domain, e);
+                        }
+                    }
+                }
+            }
+        }).await;
+        info!("缓存预热完成, 共{}个域名, 内存占用约{}字节", total, self.get_usage_bytes());
+    }
+
+    pub fn get_usage_bytes(&self) -> usize {
+        self.map.get_bytes()
     }
 
     pub async fn write_to_file(&self) -> Result<()> {
-        if self.map.is_empty() {
-            info!("没有缓存需要写入文件");
-            return Ok(());
+        self.file.write(&self.map).await
+    }
+}
+
+//上游返回的ECS scope不为0, 说明这个应答是和客户端网段相关的, 只缓存在scope和source里面短的那个网段下面
+pub fn cache_key(name: String, answer: &DnsAnswer) -> String {
+    match answer.get_client_subnet() {
+        Some(subnet) if subnet.scope_prefix > 0 => {
+            subnet_key(&name, subnet, subnet.source_prefix.min(subnet.scope_prefix))
         }
-        let mut file = File::create(&self.file_name).await?;
-        file.write_all(self.to_file_bytes().as_slice()).await?;
-        info!("缓存全部写入了文件! 文件名称是{}", self.file_name);
-        Ok(())
+        _ => name,
     }
 }
 
+fn subnet_key(name: &String, subnet: &ClientSubnet, prefix: u8) -> String {
+    format!("{}@{}", name, subnet.to_key(prefix))
+}
+
+//定时把缓存写入文件, 防止进程被杀掉或者断电之后缓存全部丢失
+fn start_snapshot_task(file: Arc<CacheFile>, map: Arc<CacheMap>, duration: Duration) {
+    tokio::spawn(async move {
+        let mut interval = interval(duration);
+        //第一次tick是立即返回的, 启动的时候不需要写
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = file.write(&map).await {
+                error!("定时把缓存写入文件出错: {:?}", e)
+            }
+        }
+    });
+}
+
 async fn create_map_by_config(config: &Config) -> Result<CacheMap> {
     Ok(match File::open(&config.cache_file).await {
         Ok(mut file) => {
             let mut file_vec = Vec::new();
             file.read_to_end(&mut file_vec).await?;
             if file_vec.is_empty() {
-                LimitedMap::from(config.cache_num)
+                LimitedMap::with_max_bytes(config.cache_num, config.cache_max_bytes)
             } else {
                 create_map_by_vec_u8(config, file_vec)
             }
         }
         Err(_e) => {
-            LimitedMap::from(config.cache_num)
+            LimitedMap::with_max_bytes(config.cache_num, config.cache_max_bytes)
         }
     })
 }
 
 fn create_map_by_vec_u8(config: &Config, file_vec: Vec<u8>) -> CacheMap {
-    let map = LimitedMap::from(config.cache_num);
-    let cursor = Cursor::form(file_vec.into());
-    let mut len = cursor.take() as usize;
-    while len > 0 {
-        let flag = cursor.peek();
-        let record = match flag {
-            IP_RECORD => {
-                CacheRecord::from(IpCacheRecord::from(cursor.take_slice(len)))
-            }
-            SOA_RECORD => {
-                CacheRecord::from(SoaCacheRecord::from(cursor.take_slice(len)))
-            }
-            _ => {
-                panic!("Unsupported cache record!");
-            }
-        };
+    let map = LimitedMap::with_max_bytes(config.cache_num, config.cache_max_bytes);
+    for record in cache_file::decode(&file_vec) {
         if !record.is_expired(get_now()) {
             map.insert(record.get_key().clone(), record);
         }
-        len = cursor.take() as usize;
     }
     map
 }
 
 #[cfg(test)]
-mod tests {}
\ No newline at end of file
+mod tests {
+    use crate::cache::{CachePool, cache_key};
+    use crate::cache::cache_file::CacheFile;
+    use crate::cache::expired_strategy::ExpiredCacheStrategy;
+    use crate::cache::limit_map::LimitedMap;
+    use crate::protocol::{DnsAnswer, DnsQuery, Ipv4Answer, ClientSubnet, LocalAnswer, LocalRecord};
+    use futures_util::FutureExt;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+
+    #[test]
+    fn should_return_name_when_call_cache_key_given_answer_without_subnet() {
+        let answer: DnsAnswer = Ipv4Answer::empty_answer(0, "www.baidu.com".into()).into();
+
+        let result = cache_key("www.baidu.com".into(), &answer);
+
+        assert_eq!("www.baidu.com", result)
+    }
+
+    #[test]
+    fn should_return_name_when_call_cache_key_given_answer_with_zero_scope() {
+        let mut answer: DnsAnswer = Ipv4Answer::empty_answer(0, "www.baidu.com".into()).into();
+        answer.set_client_subnet(get_subnet(0));
+
+        let result = cache_key("www.baidu.com".into(), &answer);
+
+        assert_eq!("www.baidu.com", result)
+    }
+
+    #[test]
+    fn should_return_subnet_key_when_call_cache_key_given_answer_with_scope() {
+        let mut answer: DnsAnswer = Ipv4Answer::empty_answer(0, "www.baidu.com".into()).into();
+        answer.set_client_subnet(get_subnet(24));
+
+        let result = cache_key("www.baidu.com".into(), &answer);
+
+        assert_eq!("www.baidu.com@1.2.3.0/24", result)
+    }
+
+    #[test]
+    fn should_return_scope_subnet_key_when_call_cache_key_given_scope_shorter_than_source() {
+        let mut answer: DnsAnswer = Ipv4Answer::empty_answer(0, "www.baidu.com".into()).into();
+        answer.set_client_subnet(get_subnet(16));
+
+        let result = cache_key("www.baidu.com".into(), &answer);
+
+        assert_eq!("www.baidu.com@1.2.0.0/16", result)
+    }
+
+    #[tokio::test]
+    async fn should_get_record_of_scope_subnet_when_get_given_client_in_same_scope() {
+        let map = Arc::new(LimitedMap::from(10));
+        let pool = CachePool {
+            strategy: Box::new(ExpiredCacheStrategy::from(map.clone())),
+            file: Arc::new(CacheFile::new("cache".into())),
+            map,
+        };
+        let query = DnsQuery::from("www.baidu.com");
+        let mut local = LocalAnswer::new(&query, 0);
+        local.add_answer(LocalRecord::ip("www.baidu.com".into(), 60, &IpAddr::V4(Ipv4Addr::new(5, 6, 7, 8))));
+        let bytes = DnsAnswer::from(local).to_bytes();
+        let mut buf = [0u8; 512];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        let mut answer = DnsAnswer::from(buf);
+        answer.set_client_subnet(get_subnet(16));
+        let client = |ip: [u8; 4]| ClientSubnet {
+            source_prefix: 24,
+            scope_prefix: 0,
+            address: IpAddr::V4(Ipv4Addr::from(ip)),
+        };
+        let not_found = || async { Err("not in cache".into()) }.boxed();
+
+        pool.get("www.baidu.com".into(), Some(&client([1, 2, 3, 4])), async { Ok(answer) }.boxed()).await.unwrap();
+        let same_scope = pool.get("www.baidu.com".into(), Some(&client([1, 2, 200, 1])), not_found()).await;
+        let other_scope = pool.get("www.baidu.com".into(), Some(&client([1, 3, 0, 1])), not_found()).await;
+
+        assert!(same_scope.is_ok());
+        assert!(other_scope.is_err());
+    }
+
+    fn get_subnet(scope_prefix: u8) -> ClientSubnet {
+        ClientSubnet {
+            source_prefix: 24,
+            scope_prefix,
+            address: IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)),
+        }
+    }
+}
\ No newline at end of file
@@ -1,5 +1,5 @@
 use std::sync::Arc;
-use crate::cache::{CacheStrategy, CacheMap, AnswerFuture};
+use crate::cache::{CacheStrategy, CacheMap, AnswerFuture, cache_key};
 use crate::system::{get_sub_now, get_now};
 use std::time::Duration;
 use crate::system::Result;
@@ -19,7 +19,7 @@ impl CacheStrategy for TimeoutCacheStrategy {
         if record.is_expired(now) {
             let answer = future.await?;
             if let Some(r) = answer.to_cache() {
-                self.map.insert(record.get_key().clone(), r);
+                self.map.insert(cache_key(record.get_key().clone(), &answer), r);
             }
             Ok(answer)
         } else {
@@ -30,7 +30,7 @@ impl CacheStrategy for TimeoutCacheStrategy {
                     match future.await {
                         Ok(answer) => {
                             if let Some(r) = answer.to_cache() {
-                                cloned_map.insert(key, r);
+                                cloned_map.insert(cache_key(key, &answer), r);
                             }
                         }
                         Err(e) => {
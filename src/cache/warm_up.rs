@@ -0,0 +1,74 @@
+use crate::resource::ResourceClient;
+use crate::system::Result;
+use std::collections::HashSet;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+pub async fn read_warm_up_domains(client: &ResourceClient, paths: &Vec<String>) -> Vec<String> {
+    let mut set = HashSet::new();
+    let mut domains = Vec::new();
+    for path in paths {
+        match read_resource_to_domains(client, path).await {
+            Ok(vec) => {
+                //去重，保持文件里面的顺序
+                vec.into_iter().for_each(|d| {
+                    if set.insert(d.clone()) {
+                        domains.push(d);
+                    }
+                });
+            }
+            Err(e) => {
+                error!("{:?}", e);
+            }
+        }
+    }
+    domains
+}
+
+async fn read_resource_to_domains(client: &ResourceClient, path: &str) -> Result<Vec<String>> {
+    let reader = client.open(path).await?;
+    read_to_domains(reader).await
+}
+
+//一行一个域名, #开头的是注释
+async fn read_to_domains(
+    mut reader: impl AsyncBufRead + std::marker::Unpin,
+) -> Result<Vec<String>> {
+    let mut buffer = String::new();
+    let mut vec = Vec::new();
+    while reader.read_line(&mut buffer).await? > 0 {
+        let line = buffer.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            if let Some(domain) = line.split_whitespace().next() {
+                vec.push(domain.trim_end_matches('.').to_lowercase());
+            }
+        }
+        buffer.clear();
+    }
+    Ok(vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::warm_up::read_warm_up_domains;
+    use crate::resource::ResourceClient;
+
+    #[tokio::test]
+    async fn should_return_distinct_domains_when_read_given_domain_files() {
+        let paths = vec!["./tests/resources/warm_up_domains.txt".to_string(),
+                         "./tests/resources/warm_up_domains.txt".to_string()];
+
+        let result = read_warm_up_domains(&ResourceClient::default(), &paths).await;
+
+        let expected: Vec<String> = vec!["www.baidu.com".into(), "www.qq.com".into()];
+        assert_eq!(expected, result)
+    }
+
+    #[tokio::test]
+    async fn should_return_empty_when_read_given_not_exist_file() {
+        let paths = vec!["./tests/resources/not_exist.txt".to_string()];
+
+        let result = read_warm_up_domains(&ResourceClient::default(), &paths).await;
+
+        assert!(result.is_empty())
+    }
+}
@@ -3,15 +3,289 @@ use tokio::fs::File;
 use tokio::io::AsyncReadExt;
 use toml::Value;
 
+#[derive(Clone, PartialEq)]
+pub struct FilterSource {
+    pub path: String,
+    //auto, smartdns, hosts, domains, adblock
+    pub format: String,
+    //多少小时重新拉取一次, 0是不刷新
+    pub refresh_duration_h: u64,
+    //被这个文件拦截的域名怎么回答, 为空就用全局的block-mode
+    pub block_mode: String,
+    //生效时间, 比如 "mon-fri 08:00-15:00", 为空就是一直生效
+    pub schedule: Vec<String>,
+}
+
+impl From<&str> for FilterSource {
+    fn from(path: &str) -> Self {
+        FilterSource {
+            path: path.into(),
+            format: "auto".into(),
+            refresh_duration_h: 0,
+            block_mode: "".into(),
+            schedule: vec![],
+        }
+    }
+}
+
+impl FilterSource {
+    //可以直接写路径, 也可以写成 { path = "...", format = "hosts", refresh-duration-h = 24, block-mode = "nxdomain" }
+    //没有path返回None
+    fn from(value: &Value) -> Option<Self> {
+        match value.as_str() {
+            Some(path) => Some(path.into()),
+            None => Some(FilterSource {
+                path: value.get("path").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+                format: value.get("format").and_then(|e| e.as_str())
+                    .map(|e| String::from(e)).unwrap_or("auto".into()),
+                refresh_duration_h: value.get("refresh-duration-h").and_then(|e| e.as_integer())
+                    .unwrap_or(0) as u64,
+                block_mode: value.get("block-mode").and_then(|e| e.as_str())
+                    .map(|e| String::from(e)).unwrap_or("".into()),
+                //可以写一个字符串, 也可以写数组
+                schedule: match value.get("schedule") {
+                    Some(Value::String(s)) => vec![s.clone()],
+                    Some(Value::Array(a)) => a.iter().filter_map(|e| {
+                        let range = e.as_str().map(|e| String::from(e));
+                        if range.is_none() {
+                            error!("filter的schedule里面的时间段格式不对, 忽略: {}", e);
+                        }
+                        range
+                    }).collect(),
+                    _ => vec![],
+                },
+            }),
+        }
+    }
+}
+
+//一组过滤规则, 全局的和每个客户端分组的都是这个
+#[derive(Clone)]
+pub struct FilterPolicy {
+    pub filters: Vec<FilterSource>,
+    pub allowlists: Vec<FilterSource>,
+    pub allow_domains: Vec<String>,
+    pub block_mode: String,
+    pub safe_search: bool,
+}
+
+impl FilterPolicy {
+    //没有写的值用parent的
+    fn from(value: &Value, parent: Option<&FilterPolicy>) -> Self {
+        let filters = value.get("filters").and_then(|e| e.as_array())
+            .map(|_| parse_array(value, "filters", |e| FilterSource::from(e)))
+            .or(parent.map(|p| p.filters.clone())).unwrap_or(vec![]);
+        let allowlists = value.get("allowlists").and_then(|e| e.as_array())
+            .map(|_| parse_array(value, "allowlists", |e| FilterSource::from(e)))
+            .or(parent.map(|p| p.allowlists.clone())).unwrap_or(vec![]);
+        let allow_domains = value.get("allow-domains").and_then(|e| e.as_array())
+            .map(|_| parse_array(value, "allow-domains", |e| e.as_str().map(|e| String::from(e))))
+            .or(parent.map(|p| p.allow_domains.clone())).unwrap_or(vec![]);
+        let block_mode = value.get("block-mode").and_then(|e| e.as_str()).map(|e| String::from(e))
+            .or(parent.map(|p| p.block_mode.clone())).unwrap_or("soa".into());
+        let safe_search = value.get("safe-search").and_then(|e| e.as_bool())
+            .or(parent.map(|p| p.safe_search)).unwrap_or(false);
+        FilterPolicy {
+            filters,
+            allowlists,
+            allow_domains,
+            block_mode,
+            safe_search,
+        }
+    }
+}
+
+//按来源地址区分的客户端分组, 每个分组有自己的过滤规则
+#[derive(Clone)]
+pub struct ClientGroup {
+    pub name: String,
+    //ip, cidr或者mac地址
+    pub clients: Vec<String>,
+    pub policy: FilterPolicy,
+}
+
+impl ClientGroup {
+    fn from(value: &Value, parent: &FilterPolicy) -> Self {
+        ClientGroup {
+            name: value.get("name").and_then(|e| e.as_str())
+                .map(|e| String::from(e)).unwrap_or("".into()),
+            clients: parse_array(value, "clients", |e| e.as_str().map(|e| String::from(e))),
+            policy: FilterPolicy::from(value, Some(parent)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LocalRecordConfig {
+    pub name: String,
+    //A, AAAA, CNAME, TXT, 不写的话按value推断, ip是A或AAAA, 其他是CNAME
+    pub _type: String,
+    pub value: String,
+    //0是用local-ttl
+    pub ttl: usize,
+}
+
+impl LocalRecordConfig {
+    fn from(value: &Value) -> Option<Self> {
+        Some(LocalRecordConfig {
+            name: value.get("name").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+            _type: value.get("type").and_then(|e| e.as_str())
+                .map(|e| String::from(e)).unwrap_or("".into()),
+            value: value.get("value").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+            ttl: value.get("ttl").and_then(|e| e.as_integer()).unwrap_or(0) as usize,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct ZoneSource {
+    pub path: String,
+    //zone文件里面没有$ORIGIN的时候用这个
+    pub origin: String,
+}
+
+impl ZoneSource {
+    //可以直接写路径, 也可以写成 { path = "./home.arpa.zone", origin = "home.arpa" }, 没有path返回None
+    fn from(value: &Value) -> Option<Self> {
+        match value.as_str() {
+            Some(path) => Some(ZoneSource {
+                path: path.into(),
+                origin: "".into(),
+            }),
+            None => Some(ZoneSource {
+                path: value.get("path").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+                origin: value.get("origin").and_then(|e| e.as_str())
+                    .map(|e| String::from(e)).unwrap_or("".into()),
+            })
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PtrForwardConfig {
+    //反向区域, 比如 "1.168.192.in-addr.arpa"
+    pub zone: String,
+    //内网的dns服务器, 比如 "192.168.1.1:53"
+    pub server: String,
+}
+
+impl PtrForwardConfig {
+    fn from(value: &Value) -> Option<Self> {
+        Some(PtrForwardConfig {
+            zone: value.get("zone").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+            server: value.get("server").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RewriteConfig {
+    //域名或者 *.后缀
+    pub from: String,
+    //from是 *.后缀 的话这里也可以用*, 代表from里面*匹配到的部分
+    pub to: String,
+}
+
+impl RewriteConfig {
+    fn from(value: &Value) -> Option<Self> {
+        Some(RewriteConfig {
+            from: value.get("from").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+            to: value.get("to").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct IpSetConfig {
+    //域名, 包括它的子域名
+    pub domains: Vec<String>,
+    //ipset的集合名, 或者nftables的 family#table#set
+    pub name: String,
+    //ipset或者nftset
+    pub _type: String,
+}
+
+impl IpSetConfig {
+    //没有name返回None
+    fn from(value: &Value) -> Option<Self> {
+        Some(IpSetConfig {
+            domains: value.get("domains").and_then(|e| e.as_array()).map(|e| {
+                e.iter().filter_map(|e| e.as_str()).map(|e| String::from(e)).collect()
+            }).unwrap_or(vec![]),
+            name: value.get("name").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+            _type: value.get("type").and_then(|e| e.as_str())
+                .map(|e| String::from(e)).unwrap_or("ipset".into()),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct IpRuleConfig {
+    //ip或者cidr, 比如 "1.2.3.4", "10.0.0.0/8"
+    pub ip: String,
+    //drop, nxdomain, polluted
+    pub action: String,
+}
+
+impl IpRuleConfig {
+    //可以直接写ip, 也可以写成 { ip = "10.0.0.0/8", action = "drop" }, 没有ip返回None
+    fn from(value: &Value) -> Option<Self> {
+        match value.as_str() {
+            Some(ip) => Some(IpRuleConfig {
+                ip: ip.into(),
+                action: "nxdomain".into(),
+            }),
+            None => Some(IpRuleConfig {
+                ip: value.get("ip").and_then(|e| e.as_str()).map(|e| String::from(e))?,
+                action: value.get("action").and_then(|e| e.as_str())
+                    .map(|e| String::from(e)).unwrap_or("nxdomain".into()),
+            })
+        }
+    }
+}
+
 pub struct Config {
     pub cache_on: bool,
     pub cache_file: String,
     pub cache_num: usize,
+    pub cache_max_bytes: usize,
+    pub cache_snapshot_duration_m: usize,
+    pub cache_warmup: Vec<String>,
+    pub cache_warmup_concurrency: usize,
     pub port: u16,
     pub servers: Vec<String>,
-    pub filters: Vec<String>,
+    pub policy: FilterPolicy,
+    pub client_groups: Vec<ClientGroup>,
+    pub block_ttl: usize,
+    //没有配置的话schedule不生效
+    pub utc_offset_m: Option<i64>,
+    pub filter_cname: bool,
+    pub local_records: Vec<LocalRecordConfig>,
+    pub local_hosts: Vec<String>,
+    pub local_ttl: usize,
+    pub zones: Vec<ZoneSource>,
+    pub private_ptr: bool,
+    pub ptr_forwards: Vec<PtrForwardConfig>,
+    pub dhcp_lease_file: String,
+    pub dhcp_lease_format: String,
+    pub dhcp_domain: String,
+    pub dhcp_ttl: usize,
+    pub dhcp_lease_check_duration_s: usize,
+    pub rewrites: Vec<RewriteConfig>,
+    pub ip_rules: Vec<IpRuleConfig>,
+    pub ip_sets: Vec<IpSetConfig>,
+    pub ip_rule_polluted_duration_m: usize,
+    pub stats_windows_m: Vec<usize>,
+    pub stats_max_domains: usize,
+    pub stats_top_n: usize,
+    pub stats_report_duration_m: usize,
+    pub resource_ca_file: String,
+    pub resource_timeout_s: usize,
+    pub resource_max_bytes: usize,
     pub log_level: String,
     pub ip_choose_strategy: usize,
+    pub ip_choose_tcp_ports: Vec<u16>,
+    pub ip_choose_tcp_timeout_ms: usize,
     pub cache_get_strategy: usize,
     pub cache_ttl_timeout_ms: usize,
     pub server_choose_strategy: usize,
@@ -19,22 +293,73 @@ pub struct Config {
 }
 
 impl Config {
-    fn from(value: Value) -> Self {
+    pub fn from(value: Value) -> Self {
         let cache_file = value["cache-file"].as_str().map(|e| String::from(e))
             .unwrap_or("cache".into());
         let cache_on = value["cache"].as_bool().unwrap_or(true);
         let cache_num = value["cache-num"].as_integer().unwrap_or(1000) as usize;
+        let cache_max_bytes = value.get("cache-max-bytes").and_then(|e| e.as_integer())
+            .unwrap_or(0) as usize;
+        let cache_snapshot_duration_m = value.get("cache-snapshot-duration-m")
+            .and_then(|e| e.as_integer()).unwrap_or(10) as usize;
+        let cache_warmup = parse_array(&value, "cache-warmup", |e| e.as_str().map(|e| String::from(e)));
+        let cache_warmup_concurrency = value.get("cache-warmup-concurrency")
+            .and_then(|e| e.as_integer()).unwrap_or(8) as usize;
         let port = value["port"].as_integer().unwrap_or(2053) as u16;
         let servers = value["servers"].as_array().map(|e| {
             e.iter().map(|e| String::from(e.as_str().unwrap())).collect()
         }).unwrap_or(vec![]);
-        let filters = value["filters"].as_array().map(|e| {
-            e.iter().map(|e| String::from(e.as_str().unwrap())).collect()
+        let policy = FilterPolicy::from(&value, None);
+        let client_groups = value.get("client-groups").and_then(|e| e.as_array()).map(|e| {
+            e.iter().map(|e| ClientGroup::from(e, &policy)).collect()
         }).unwrap_or(vec![]);
+        let block_ttl = value.get("block-ttl").and_then(|e| e.as_integer()).unwrap_or(600) as usize;
+        let utc_offset_m = value.get("utc-offset-m").and_then(|e| e.as_integer());
+        let filter_cname = value.get("filter-cname").and_then(|e| e.as_bool()).unwrap_or(true);
+        let local_records = parse_array(&value, "local-records", |e| LocalRecordConfig::from(e));
+        let local_hosts = parse_array(&value, "local-hosts", |e| e.as_str().map(|e| String::from(e)));
+        let local_ttl = value.get("local-ttl").and_then(|e| e.as_integer()).unwrap_or(300) as usize;
+        let zones = parse_array(&value, "zones", |e| ZoneSource::from(e));
+        let private_ptr = value.get("private-ptr").and_then(|e| e.as_bool()).unwrap_or(true);
+        let ptr_forwards = parse_array(&value, "ptr-forwards", |e| PtrForwardConfig::from(e));
+        let dhcp_lease_file = value.get("dhcp-lease-file").and_then(|e| e.as_str())
+            .map(|e| String::from(e)).unwrap_or("".into());
+        let dhcp_lease_format = value.get("dhcp-lease-format").and_then(|e| e.as_str())
+            .map(|e| String::from(e)).unwrap_or("dnsmasq".into());
+        let dhcp_domain = value.get("dhcp-domain").and_then(|e| e.as_str())
+            .map(|e| String::from(e)).unwrap_or("lan".into());
+        let dhcp_ttl = value.get("dhcp-ttl").and_then(|e| e.as_integer()).unwrap_or(60) as usize;
+        let dhcp_lease_check_duration_s = value.get("dhcp-lease-check-duration-s")
+            .and_then(|e| e.as_integer()).unwrap_or(10) as usize;
+        let rewrites = parse_array(&value, "rewrites", |e| RewriteConfig::from(e));
+        let ip_rules = parse_array(&value, "ip-rules", |e| IpRuleConfig::from(e));
+        let ip_sets = parse_array(&value, "ip-sets", |e| IpSetConfig::from(e));
+        let ip_rule_polluted_duration_m = value.get("ip-rule-polluted-duration-m")
+            .and_then(|e| e.as_integer()).unwrap_or(30) as usize;
+        let stats_windows_m = value.get("stats-windows-m").and_then(|e| e.as_array())
+            .map(|_| parse_array(&value, "stats-windows-m", |e| e.as_integer().map(|e| e as usize)))
+            .unwrap_or(vec![60, 24 * 60]);
+        let stats_max_domains = value.get("stats-max-domains").and_then(|e| e.as_integer())
+            .unwrap_or(1000) as usize;
+        let stats_top_n = value.get("stats-top-n").and_then(|e| e.as_integer())
+            .unwrap_or(10) as usize;
+        let stats_report_duration_m = value.get("stats-report-duration-m")
+            .and_then(|e| e.as_integer()).unwrap_or(0) as usize;
+        let resource_ca_file = value.get("resource-ca-file").and_then(|e| e.as_str())
+            .map(|e| String::from(e)).unwrap_or("".into());
+        let resource_timeout_s = value.get("resource-timeout-s").and_then(|e| e.as_integer())
+            .unwrap_or(60) as usize;
+        let resource_max_bytes = value.get("resource-max-bytes").and_then(|e| e.as_integer())
+            .unwrap_or(32 * 1024 * 1024) as usize;
         let log_level = value["log-level"].as_str().map(|e| String::from(e))
             .unwrap_or("error".into());
         let ip_choose_strategy = value["ip-choose-strategy"].as_integer()
             .unwrap_or(0) as usize;
+        let ip_choose_tcp_ports = value.get("ip-choose-tcp-ports").and_then(|e| e.as_array())
+            .map(|e| e.iter().filter_map(|e| e.as_integer()).map(|e| e as u16).collect())
+            .unwrap_or(vec![443, 80]);
+        let ip_choose_tcp_timeout_ms = value.get("ip-choose-tcp-timeout-ms")
+            .and_then(|e| e.as_integer()).unwrap_or(1000) as usize;
         let cache_get_strategy = value["cache-get-strategy"].as_integer()
             .unwrap_or(0) as usize;
         let cache_ttl_timeout_ms = value["cache-ttl-timeout-ms"].as_integer()
@@ -47,11 +372,43 @@ impl Config {
             cache_on,
             cache_file,
             cache_num,
+            cache_max_bytes,
+            cache_snapshot_duration_m,
+            cache_warmup,
+            cache_warmup_concurrency,
             port,
             servers,
-            filters,
+            policy,
+            client_groups,
+            block_ttl,
+            utc_offset_m,
+            filter_cname,
+            local_records,
+            local_hosts,
+            local_ttl,
+            zones,
+            private_ptr,
+            ptr_forwards,
+            dhcp_lease_file,
+            dhcp_lease_format,
+            dhcp_domain,
+            dhcp_ttl,
+            dhcp_lease_check_duration_s,
+            rewrites,
+            ip_rules,
+            ip_sets,
+            ip_rule_polluted_duration_m,
+            stats_windows_m,
+            stats_max_domains,
+            stats_top_n,
+            stats_report_duration_m,
+            resource_ca_file,
+            resource_timeout_s,
+            resource_max_bytes,
             log_level,
             ip_choose_strategy,
+            ip_choose_tcp_ports,
+            ip_choose_tcp_timeout_ms,
             cache_get_strategy,
             cache_ttl_timeout_ms,
             server_choose_strategy,
@@ -60,6 +417,19 @@ impl Config {
     }
 }
 
+//数组里面格式不对的项写日志后跳过, 不影响其他的配置
+fn parse_array<T>(value: &Value, key: &str, parse: impl Fn(&Value) -> Option<T>) -> Vec<T> {
+    value.get(key).and_then(|e| e.as_array()).map(|e| {
+        e.iter().filter_map(|item| {
+            let result = parse(item);
+            if result.is_none() {
+                error!("{}里面的配置格式不对, 忽略: {}", key, item);
+            }
+            result
+        }).collect()
+    }).unwrap_or(vec![])
+}
+
 pub async fn init_from_toml() -> Result<Config> {
     let mut file = File::open("easydns.toml").await?;
     let buf = &mut String::new();
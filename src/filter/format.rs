@@ -0,0 +1,151 @@
+use crate::filter::{FilterItem, RuleKind};
+use regex::Regex;
+use std::net::IpAddr;
+
+//hosts文件里面这些不是要拦截的域名
+const HOSTS_IGNORED: [&str; 6] = ["localhost", "localhost.localdomain", "local",
+    "broadcasthost", "ip6-localhost", "ip6-loopback"];
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum FilterFormat {
+    //每一行自动识别格式
+    Auto,
+    //address /00-gov.cn/#
+    Smartdns,
+    //0.0.0.0 00-gov.cn
+    Hosts,
+    //00-gov.cn, 一行一个域名
+    Domains,
+    //||00-gov.cn^ 和 @@||00-gov.cn^, @@开头的是allow规则
+    Adblock,
+}
+
+impl From<&str> for FilterFormat {
+    fn from(format: &str) -> Self {
+        match format.to_lowercase().as_str() {
+            "auto" => FilterFormat::Auto,
+            "smartdns" => FilterFormat::Smartdns,
+            "hosts" => FilterFormat::Hosts,
+            "domains" => FilterFormat::Domains,
+            "adblock" => FilterFormat::Adblock,
+            _ => {
+                error!("不支持的filter格式: {}, 将会自动识别", format);
+                FilterFormat::Auto
+            }
+        }
+    }
+}
+
+impl FilterFormat {
+    pub fn detect(line: &str) -> Self {
+        let first = line.split_whitespace().next().unwrap_or("");
+        if line.starts_with("address ") {
+            FilterFormat::Smartdns
+        } else if line.starts_with("||") || line.starts_with("@@||") {
+            FilterFormat::Adblock
+        } else if first.parse::<IpAddr>().is_ok() {
+            FilterFormat::Hosts
+        } else {
+            FilterFormat::Domains
+        }
+    }
+}
+
+//hosts文件里面是精确的主机名, 所以用精确匹配
+pub fn parse_hosts_line(domain_regex: &Regex, line: &str) -> Vec<FilterItem> {
+    let line = line.split('#').next().unwrap_or("");
+    let mut split = line.split_whitespace();
+    if split.next().and_then(|ip| ip.parse::<IpAddr>().ok()).is_none() {
+        return vec![];
+    }
+    split.filter(|host| !HOSTS_IGNORED.contains(host) && domain_regex.is_match(host))
+        .map(|host| new_item(host, RuleKind::Exact, "#"))
+        .collect()
+}
+
+//一行一个域名, 和smartdns格式一样匹配域名本身和所有的子域名
+pub fn parse_domain_line(domain_regex: &Regex, line: &str) -> Vec<FilterItem> {
+    let line = line.split('#').next().unwrap_or("").trim();
+    let domain = line.trim_start_matches("*.").trim_end_matches('.');
+    if domain_regex.is_match(domain) {
+        vec![new_item(domain, RuleKind::Suffix, "#")]
+    } else {
+        vec![]
+    }
+}
+
+//只支持dns相关的||domain^写法, 带$选项的是给浏览器用的, 忽略
+pub fn parse_adblock_line(domain_regex: &Regex, line: &str) -> Vec<FilterItem> {
+    let line = line.trim();
+    let (body, group) = match line.strip_prefix("@@") {
+        Some(body) => (body, "@"),
+        None => (line, "#"),
+    };
+    let domain = match body.strip_prefix("||").and_then(|b| b.strip_suffix('^')) {
+        Some(domain) => domain,
+        None => return vec![],
+    };
+    if domain_regex.is_match(domain) {
+        vec![new_item(domain, RuleKind::Suffix, group)]
+    } else {
+        vec![]
+    }
+}
+
+fn new_item(domain: &str, kind: RuleKind, group: &str) -> FilterItem {
+    FilterItem {
+        domain: domain.to_lowercase(),
+        kind,
+        group: group.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::format::{FilterFormat, parse_hosts_line, parse_domain_line, parse_adblock_line};
+    use crate::filter::{DOMAIN_REGEX, RuleKind};
+    use regex::Regex;
+
+    #[test]
+    fn should_return_format_when_detect_given_lines() {
+        assert_eq!(FilterFormat::Smartdns, FilterFormat::detect("address /a.com/#"));
+        assert_eq!(FilterFormat::Adblock, FilterFormat::detect("@@||a.com^"));
+        assert_eq!(FilterFormat::Hosts, FilterFormat::detect("0.0.0.0 a.com"));
+        assert_eq!(FilterFormat::Hosts, FilterFormat::detect(":: a.com"));
+        assert_eq!(FilterFormat::Domains, FilterFormat::detect("a.com"));
+    }
+
+    #[test]
+    fn should_return_exact_items_when_parse_hosts_given_multi_hosts_line() {
+        let regex = Regex::new(DOMAIN_REGEX).unwrap();
+
+        let result = parse_hosts_line(&regex, "0.0.0.0 a.com B.com localhost # comment c.com");
+
+        let domains: Vec<&str> = result.iter().map(|i| i.domain.as_str()).collect();
+        assert_eq!(vec!["a.com", "b.com"], domains);
+        assert!(result.iter().all(|i| i.kind == RuleKind::Exact));
+    }
+
+    #[test]
+    fn should_return_suffix_item_when_parse_domain_given_domain_line() {
+        let regex = Regex::new(DOMAIN_REGEX).unwrap();
+
+        let result = parse_domain_line(&regex, "a.com.\n");
+
+        assert_eq!(1, result.len());
+        assert_eq!(("a.com", RuleKind::Suffix), (result[0].domain.as_str(), result[0].kind));
+    }
+
+    #[test]
+    fn should_return_allow_item_when_parse_adblock_given_exception_line() {
+        let regex = Regex::new(DOMAIN_REGEX).unwrap();
+
+        let block = parse_adblock_line(&regex, "||a.com^");
+        let allow = parse_adblock_line(&regex, "@@||b.com^\n");
+        let option = parse_adblock_line(&regex, "||c.com^$third-party");
+
+        assert_eq!(("a.com", "#"), (block[0].domain.as_str(), block[0].group.as_str()));
+        assert_eq!(("b.com", "@"), (allow[0].domain.as_str(), allow[0].group.as_str()));
+        assert!(option.is_empty());
+    }
+}
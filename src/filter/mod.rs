@@ -0,0 +1,514 @@
+mod trie;
+mod format;
+mod refresh;
+mod schedule;
+mod stats;
+
+use crate::system::Result;
+use regex::{Regex, RegexSet};
+use std::collections::HashSet;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use trie::DomainTrie;
+use format::{FilterFormat, parse_hosts_line, parse_domain_line, parse_adblock_line};
+use schedule::Schedule;
+
+pub use refresh::FilterHolder;
+pub use stats::{FilterStats, StatsReport};
+
+const GET_RULE_REGEX: &str = "^address /(.+)/([#d])\\s*$";
+const DOMAIN_REGEX: &str =
+    "^[a-zA-Z0-9][-a-zA-Z0-9]{0,62}(?:\\.[a-zA-Z0-9][-a-zA-Z0-9]{0,62})+$";
+const WILDCARD_REGEX: &str = "^[-a-zA-Z0-9.]*\\*[-a-zA-Z0-9.*]*$";
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+enum RuleKind {
+    //匹配域名本身和所有的子域名, address /ads.com/#
+    Suffix,
+    //只匹配域名本身, address /=ads.com/#
+    Exact,
+    //*可以匹配任意字符, address /*.ads.*/#
+    Wildcard,
+    //正则表达式, 需要显式的用~开头, address /~^ad[0-9]+\./#
+    Regex,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+struct FilterItem {
+    domain: String,
+    kind: RuleKind,
+    group: String,
+}
+
+impl From<String> for FilterItem {
+    fn from(domain: String) -> Self {
+        FilterItem {
+            domain,
+            kind: RuleKind::Suffix,
+            group: "#".into(),
+        }
+    }
+}
+
+pub struct Filter {
+    block: RuleSet,
+    //只在一段时间内生效的filter, 每个单独一个RuleSet
+    scheduled: Vec<(Schedule, RuleSet)>,
+    allow: RuleSet,
+}
+
+impl Filter {
+    fn create(mut block: Rules, mut scheduled: Vec<(Schedule, Rules)>, mut allow: Rules) -> Self {
+        //blocklist里面的例外规则也是allow规则
+        let mut exceptions = std::mem::take(&mut block.exceptions);
+        scheduled.iter_mut().for_each(|(_, rules)| exceptions.append(&mut rules.exceptions));
+        exceptions.append(&mut allow.exceptions);
+        exceptions.into_iter().for_each(|mut item| {
+            item.group = "#".into();
+            allow.apply(item, 0);
+        });
+        Filter {
+            block: block.into(),
+            scheduled: scheduled.into_iter().map(|(s, rules)| (s, rules.into())).collect(),
+            allow: allow.into(),
+        }
+    }
+
+    pub fn contain(&self, domain: &String) -> bool {
+        self.find(domain).is_some()
+    }
+
+    //返回拦截这个域名的规则是filters里面的第几个, 没有被拦截就返回None
+    //allow和block都匹配的话, 最具体的规则生效, 一样具体的话allow生效
+    //有生效时间的filter按当前的本地时间判断, 不在生效时间内的不拦截
+    pub fn find(&self, domain: &String) -> Option<usize> {
+        let scheduled = self.scheduled.iter()
+            .filter_map(|(schedule, rules)| rules.find(domain).filter(|_| schedule.is_active()));
+        let (block, source) = self.block.find(domain).into_iter().chain(scheduled)
+            .max_by_key(|(depth, _)| *depth)?;
+        match self.allow.find(domain) {
+            Some((allow, _)) if allow >= block => None,
+            _ => Some(source),
+        }
+    }
+}
+
+struct RuleSet {
+    trie: DomainTrie<usize>,
+    exact: DomainTrie<usize>,
+    patterns: RegexSet,
+    pattern_sources: Vec<usize>,
+}
+
+impl RuleSet {
+    //返回匹配到的规则有多具体和规则来自第几个文件
+    //精确匹配就是域名的label数量, 上级域名匹配就是规则的label数量, 通配符和正则规则算作最不具体的
+    fn find(&self, domain: &String) -> Option<(usize, usize)> {
+        if let Some(source) = self.exact.get(domain) {
+            return Some((domain.split('.').count(), *source));
+        }
+        if let Some((depth, source)) = self.trie.find_with_depth(domain) {
+            return Some((depth, *source));
+        }
+        self.patterns.matches(domain).into_iter().next()
+            .map(|index| (0, self.pattern_sources[index]))
+    }
+}
+
+//从多个文件中读出来的规则, 后面的文件可以删除前面文件的规则, 值是规则来自第几个文件
+struct Rules {
+    trie: DomainTrie<usize>,
+    exact: DomainTrie<usize>,
+    patterns: Vec<(String, usize)>,
+    //adblock格式里面@@开头的例外规则
+    exceptions: Vec<FilterItem>,
+}
+
+impl Rules {
+    fn new() -> Self {
+        Rules {
+            trie: DomainTrie::new(),
+            exact: DomainTrie::new(),
+            patterns: Vec::new(),
+            exceptions: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, item: FilterItem, source: usize) {
+        if item.group == "@" {
+            self.exceptions.push(item);
+            return;
+        }
+        let add = item.group == "#";
+        match item.kind {
+            RuleKind::Suffix | RuleKind::Exact => {
+                let trie = if item.kind == RuleKind::Suffix { &mut self.trie } else { &mut self.exact };
+                if add {
+                    trie.insert(&item.domain, source);
+                } else {
+                    trie.remove(&item.domain);
+                }
+            }
+            RuleKind::Wildcard | RuleKind::Regex => {
+                let pattern = if item.kind == RuleKind::Wildcard {
+                    wildcard_to_regex(&item.domain)
+                } else {
+                    item.domain
+                };
+                if add {
+                    match self.patterns.iter_mut().find(|(p, _)| p == &pattern) {
+                        Some(exist) => exist.1 = source,
+                        None => self.patterns.push((pattern, source)),
+                    }
+                } else {
+                    self.patterns.retain(|(p, _)| p != &pattern);
+                }
+            }
+        }
+    }
+}
+
+impl From<Rules> for RuleSet {
+    fn from(rules: Rules) -> Self {
+        //所有的通配符和正则规则编译成一个RegexSet, 一次匹配完
+        let (patterns, pattern_sources): (Vec<String>, Vec<usize>) = rules.patterns.into_iter().unzip();
+        let patterns = RegexSet::new(&patterns).unwrap_or_else(|e| {
+            error!("filter regex rules compile error, all regex rules are ignored: {:?}", e);
+            RegexSet::empty()
+        });
+        RuleSet {
+            trie: rules.trie,
+            exact: rules.exact,
+            patterns,
+            pattern_sources,
+        }
+    }
+}
+
+impl From<Rules> for Filter {
+    fn from(rules: Rules) -> Self {
+        Filter::create(rules, vec![], Rules::new())
+    }
+}
+
+fn wildcard_to_regex(wildcard: &str) -> String {
+    let body: Vec<String> = wildcard.split('*').map(|s| regex::escape(s)).collect();
+    format!("(?i)^{}$", body.join(".*"))
+}
+
+//按文件的顺序合并规则, 同一个文件里面先加再删, source是文件在配置里面的序号
+fn build_rules<'a>(sources: impl Iterator<Item=(usize, &'a HashSet<FilterItem>)>) -> Rules {
+    let mut rules = Rules::new();
+    for (source, items) in sources {
+        let (add, delete): (Vec<&FilterItem>, Vec<&FilterItem>) = items.iter()
+            .partition(|f| f.group == "#");
+        for f in add.into_iter().chain(delete) {
+            rules.apply(f.clone(), source);
+        }
+    }
+    rules
+}
+
+async fn read_to_filter(
+    mut reader: impl AsyncBufRead + std::marker::Unpin,
+    format: FilterFormat,
+) -> Result<HashSet<FilterItem>> {
+    let mut buffer = String::new();
+    let parser = LineParser::new();
+    let mut set = HashSet::new();
+    while reader.read_line(&mut buffer).await? > 0 {
+        parser.parse_with_format(&buffer, format).into_iter().for_each(|item| {
+            set.insert(item);
+        });
+        buffer.clear();
+    }
+    Ok(set)
+}
+
+struct LineParser {
+    line: Regex,
+    domain: Regex,
+    wildcard: Regex,
+}
+
+impl LineParser {
+    fn new() -> Self {
+        LineParser {
+            line: Regex::new(GET_RULE_REGEX).unwrap(),
+            domain: Regex::new(DOMAIN_REGEX).unwrap(),
+            wildcard: Regex::new(WILDCARD_REGEX).unwrap(),
+        }
+    }
+
+    fn parse_with_format(&self, line: &String, format: FilterFormat) -> Vec<FilterItem> {
+        let trimmed = line.trim();
+        //adblock格式的注释是!开头的
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('!') {
+            return vec![];
+        }
+        let format = if format == FilterFormat::Auto {
+            FilterFormat::detect(trimmed)
+        } else {
+            format
+        };
+        match format {
+            FilterFormat::Auto | FilterFormat::Smartdns => self.parse(line).into_iter().collect(),
+            FilterFormat::Hosts => parse_hosts_line(&self.domain, trimmed),
+            FilterFormat::Domains => parse_domain_line(&self.domain, trimmed),
+            FilterFormat::Adblock => parse_adblock_line(&self.domain, trimmed),
+        }
+    }
+
+    fn parse(&self, line: &String) -> Option<FilterItem> {
+        if line.starts_with("#") {
+            return None;
+        }
+        let cap = self.line.captures(line)?;
+        let body = cap.get(1)?.as_str();
+        let group = String::from(cap.get(2)?.as_str());
+        let (domain, kind) = if let Some(pattern) = body.strip_prefix('~') {
+            //正则不合法的话忽略这一行
+            if let Err(e) = Regex::new(pattern) {
+                debug!("filter regex rule is illegal: {}, {:?}", line, e);
+                return None;
+            }
+            (pattern, RuleKind::Regex)
+        } else if let Some(domain) = body.strip_prefix('=') {
+            (domain, RuleKind::Exact)
+        } else if self.wildcard.is_match(body) {
+            (body, RuleKind::Wildcard)
+        } else {
+            (body, RuleKind::Suffix)
+        };
+        if (kind == RuleKind::Suffix || kind == RuleKind::Exact) && !self.domain.is_match(domain) {
+            debug!("filter rule is illegal: {}", line);
+            return None;
+        }
+        Some(FilterItem {
+            domain: String::from(domain),
+            kind,
+            group,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::{
+        LineParser, read_to_filter, build_rules, FilterItem, RuleKind, Rules, Filter,
+    };
+    use crate::filter::format::FilterFormat;
+    use crate::filter::schedule::Schedule;
+    use crate::config::FilterSource;
+    use crate::system::set_time_base;
+    use crate::resource::ResourceClient;
+    use crate::system::Result;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_handle_one_line() {
+        let parser = LineParser::new();
+        let x = String::from("address /kwcscdn.000dn.com/#");
+
+        let result = parser.parse(&x);
+
+        assert_eq!(result, Some(String::from("kwcscdn.000dn.com").into()));
+    }
+
+    #[test]
+    fn should_return_rule_kind_when_parse_given_exact_wildcard_and_regex_lines() {
+        let parser = LineParser::new();
+
+        let exact = parser.parse(&"address /=ads.com/#".into()).unwrap();
+        let wildcard = parser.parse(&"address /*.ads.*/d".into()).unwrap();
+        let regex = parser.parse(&"address /~^ad[0-9]+/x\\./#".into()).unwrap();
+
+        assert_eq!((RuleKind::Exact, "ads.com"), (exact.kind, exact.domain.as_str()));
+        assert_eq!((RuleKind::Wildcard, "d"), (wildcard.kind, wildcard.group.as_str()));
+        assert_eq!((RuleKind::Regex, "^ad[0-9]+/x\\."), (regex.kind, regex.domain.as_str()));
+    }
+
+    #[test]
+    fn should_return_none_when_parse_given_illegal_regex() {
+        let parser = LineParser::new();
+
+        let result = parser.parse(&"address /~ad[/#".into());
+
+        assert_eq!(None, result)
+    }
+
+    #[test]
+    fn should_match_rules_when_call_contain_given_filter_with_all_kinds() {
+        let parser = LineParser::new();
+        let mut rules = Rules::new();
+        vec!["address /ads.com/#", "address /=exact.com/#", "address /*.track.*/#",
+             "address /~^ad[0-9]+\\./#"].into_iter().for_each(|line| {
+            rules.apply(parser.parse(&line.into()).unwrap(), 0);
+        });
+        let filter: Filter = rules.into();
+
+        assert!(filter.contain(&"www.ads.com".into()));
+        assert!(filter.contain(&"exact.com".into()));
+        assert!(!filter.contain(&"www.exact.com".into()));
+        assert!(filter.contain(&"a.track.example.com".into()));
+        assert!(!filter.contain(&"track.example.com".into()));
+        assert!(filter.contain(&"ad01.example.com".into()));
+        assert!(!filter.contain(&"www.example.com".into()));
+    }
+
+    #[test]
+    fn should_remove_pattern_when_apply_given_delete_item() {
+        let parser = LineParser::new();
+        let mut rules = Rules::new();
+
+        rules.apply(parser.parse(&"address /*.track.*/#".into()).unwrap(), 0);
+        rules.apply(parser.parse(&"address /*.track.*/d".into()).unwrap(), 1);
+
+        assert!(rules.patterns.is_empty())
+    }
+
+    #[tokio::test]
+    async fn test_read_file_to_filter() -> Result<()> {
+        let filter = read_resource_to_filter(
+            &"./tests/resources/test_filter.txt".into()).await?;
+
+        let mut expected: HashSet<FilterItem> = HashSet::new();
+        expected.insert(String::from("00-gov.cn").into());
+        expected.insert(String::from("kwcdn.000dn.com").into());
+        assert_eq!(expected, filter);
+        Ok(())
+    }
+
+    // #[tokio::test]
+    // async fn test_read_url_to_filter() -> Result<()> {
+    //     let filter = read_resource_to_filter(
+    //         "https://raw.githubusercontent.com/dunmengjun\
+    //         /SmartDNS-GFWList/master/test_url_filter.txt",
+    //     ).await?;
+    //
+    //     let mut expected: HashSet<FilterItem> = HashSet::new();
+    //     expected.insert(String::from("00-gov.cn").into());
+    //     expected.insert(String::from("kwcdn.000dn.com").into());
+    //     assert_eq!(expected, filter);
+    //     Ok(())
+    // }
+
+    #[tokio::test]
+    async fn test_filter_item_overcast() -> Result<()> {
+        let filters: Vec<FilterSource> = vec!["./tests/resources/test_filter.txt".into(),
+                                        "./tests/resources/covercast_filter.txt".into()];
+
+        let result = read_resources_to_filter(&filters).await;
+
+        assert_eq!(1, result.trie.len());
+        assert_eq!(Some(&0), result.trie.get("00-gov.cn"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_read_all_formats_when_read_given_mixed_format_file() -> Result<()> {
+        let filters: Vec<FilterSource> = vec!["./tests/resources/mixed_format_filter.txt".into()];
+
+        let result = read_resources_to_filter(&filters).await;
+
+        let filter: Filter = result.into();
+        assert!(filter.contain(&"www.smartdns.com".into()));
+        assert!(filter.contain(&"hosts.com".into()));
+        assert!(!filter.contain(&"www.hosts.com".into()));
+        assert!(filter.contain(&"www.domains.com".into()));
+        assert!(filter.contain(&"www.adblock.com".into()));
+        assert!(!filter.contain(&"allow.com".into()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_return_source_index_when_find_given_multi_filter_files() -> Result<()> {
+        let filters: Vec<FilterSource> = vec!["./tests/resources/test_filter.txt".into(),
+                                              "./tests/resources/mixed_format_filter.txt".into()];
+
+        let filter: Filter = read_resources_to_filter(&filters).await.into();
+
+        assert_eq!(Some(0), filter.find(&"www.00-gov.cn".into()));
+        assert_eq!(Some(1), filter.find(&"hosts.com".into()));
+        assert_eq!(None, filter.find(&"allow.com".into()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_only_read_configured_format_when_read_given_hosts_format() -> Result<()> {
+        let filters = vec![FilterSource {
+            path: "./tests/resources/mixed_format_filter.txt".into(),
+            format: "hosts".into(),
+            refresh_duration_h: 0,
+            block_mode: "".into(),
+            schedule: vec![],
+        }];
+
+        let result = read_resources_to_filter(&filters).await;
+
+        assert_eq!(1, result.exact.len());
+        assert!(result.trie.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn should_return_most_specific_rule_when_call_contain_given_allow_and_block() {
+        let parser = LineParser::new();
+        let mut block = Rules::new();
+        let mut allow = Rules::new();
+        vec!["address /ads.com/#", "address /=www.good.ads.com/#", "address /*.track.*/#"]
+            .into_iter().for_each(|line| block.apply(parser.parse(&line.into()).unwrap(), 0));
+        vec!["address /good.ads.com/#", "address /example.com/#"]
+            .into_iter().for_each(|line| allow.apply(parser.parse(&line.into()).unwrap(), 0));
+
+        let filter = Filter::create(block, vec![], allow);
+
+        assert!(filter.contain(&"www.ads.com".into()));
+        assert!(!filter.contain(&"good.ads.com".into()));
+        assert!(!filter.contain(&"a.good.ads.com".into()));
+        assert!(filter.contain(&"www.good.ads.com".into()));
+        assert!(!filter.contain(&"a.track.example.com".into()));
+        assert!(filter.contain(&"a.track.other.com".into()));
+    }
+
+    #[test]
+    fn should_only_block_in_schedule_when_find_given_scheduled_rules() {
+        let parser = LineParser::new();
+        let mut scheduled = Rules::new();
+        scheduled.apply(parser.parse(&"address /game.com/#".into()).unwrap(), 1);
+        //2021-08-02 08:00:00 UTC, 星期一
+        let schedule = Schedule::parse(&["mon-fri 08:00-15:00".into()], 0).unwrap();
+        let filter = Filter::create(Rules::new(), vec![(schedule, scheduled)], Rules::new());
+
+        set_time_base(1627891200000);
+        let in_schedule = filter.find(&"www.game.com".into());
+        set_time_base(1627891200000 + 8 * 60 * 60 * 1000);
+        let out_of_schedule = filter.find(&"www.game.com".into());
+
+        assert_eq!(Some(1), in_schedule);
+        assert_eq!(None, out_of_schedule);
+    }
+
+    async fn read_resource_to_filter(source: &FilterSource) -> Result<HashSet<FilterItem>> {
+        let reader = ResourceClient::default().open(&source.path).await?;
+        read_to_filter(reader, FilterFormat::from(source.format.as_str())).await
+    }
+
+    async fn read_resources_to_filter(sources: &Vec<FilterSource>) -> Rules {
+        let mut vec = Vec::new();
+        for source in sources {
+            vec.push(read_resource_to_filter(source).await.unwrap());
+        }
+        build_rules(vec.iter().enumerate())
+    }
+
+    #[tokio::test]
+    async fn test_filter_path_empty() -> Result<()> {
+        let filters: Vec<FilterSource> = vec![];
+
+        let result = read_resources_to_filter(&filters).await;
+
+        assert!(result.trie.is_empty());
+        assert!(result.patterns.is_empty());
+        Ok(())
+    }
+}
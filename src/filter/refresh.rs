@@ -0,0 +1,246 @@
+use crate::config::{FilterPolicy, FilterSource};
+use crate::filter::{Filter, FilterItem, build_rules, read_to_filter};
+use crate::filter::format::FilterFormat;
+use crate::filter::schedule::Schedule;
+use crate::resource::{ResourceClient, Validators};
+use crate::system::Result;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+//查询的时候从这里拿当前的Filter, 刷新之后整体替换, 正在处理的查询继续用旧的Filter
+pub struct FilterHolder {
+    filter: RwLock<Arc<Filter>>,
+}
+
+impl FilterHolder {
+    pub async fn from(policy: &FilterPolicy, utc_offset_m: Option<i64>, client: ResourceClient) -> Arc<Self> {
+        let loader = FilterLoader::from(policy, utc_offset_m, client).await;
+        let holder = Arc::new(FilterHolder {
+            filter: RwLock::new(Arc::new(loader.build())),
+        });
+        //没有需要刷新的就不用保存解析后的规则了
+        if loader.states.iter().any(|s| s.source.refresh_duration_h > 0) {
+            start_refresh_tasks(holder.clone(), loader);
+        }
+        holder
+    }
+
+    pub fn get(&self) -> Arc<Filter> {
+        self.filter.read().unwrap().clone()
+    }
+
+    fn replace(&self, filter: Filter) {
+        *self.filter.write().unwrap() = Arc::new(filter);
+    }
+}
+
+struct SourceState {
+    source: FilterSource,
+    allow: bool,
+    items: HashSet<FilterItem>,
+    validators: Validators,
+    //source在filters或者allowlists里面的序号
+    index: usize,
+    schedule: Option<Schedule>,
+}
+
+impl SourceState {
+    async fn load(client: &ResourceClient, source: &FilterSource, allow: bool, index: usize,
+                  utc_offset_m: Option<i64>) -> Self {
+        let schedule = if source.schedule.is_empty() {
+            None
+        } else if let Some(utc_offset_m) = utc_offset_m {
+            let schedule = Schedule::parse(&source.schedule, utc_offset_m);
+            if schedule.is_none() {
+                error!("filter {} 的schedule格式不对, 将会一直生效: {:?}", source.path, source.schedule);
+            }
+            schedule
+        } else {
+            warn!("filter {} 配置了schedule, 但是没有配置utc-offset-m, 将会一直生效", source.path);
+            None
+        };
+        let mut state = SourceState {
+            source: source.clone(),
+            allow,
+            items: HashSet::new(),
+            validators: Validators::default(),
+            index,
+            schedule,
+        };
+        if let Err(e) = state.refresh(client).await {
+            error!("{:?}", e);
+        }
+        state
+    }
+
+    //规则有变化的话返回true
+    async fn refresh(&mut self, client: &ResourceClient) -> Result<bool> {
+        let (reader, validators) = match client.open_if_modified(
+            &self.source.path, &self.validators).await? {
+            Some(result) => result,
+            None => return Ok(false),
+        };
+        let items = read_to_filter(reader, FilterFormat::from(self.source.format.as_str())).await?;
+        self.validators = validators;
+        if items == self.items {
+            return Ok(false);
+        }
+        self.items = items;
+        Ok(true)
+    }
+}
+
+struct FilterLoader {
+    client: ResourceClient,
+    states: Vec<SourceState>,
+    allow_domains: Vec<String>,
+}
+
+impl FilterLoader {
+    async fn from(policy: &FilterPolicy, utc_offset_m: Option<i64>, client: ResourceClient) -> Self {
+        let mut states = Vec::new();
+        for (index, source) in policy.filters.iter().enumerate() {
+            states.push(SourceState::load(&client, source, false, index, utc_offset_m).await);
+        }
+        for (index, source) in policy.allowlists.iter().enumerate() {
+            states.push(SourceState::load(&client, source, true, index, utc_offset_m).await);
+        }
+        FilterLoader {
+            client,
+            states,
+            allow_domains: policy.allow_domains.clone(),
+        }
+    }
+
+    fn build(&self) -> Filter {
+        let block = build_rules(self.states.iter()
+            .filter(|s| !s.allow && s.schedule.is_none())
+            .map(|s| (s.index, &s.items)));
+        let scheduled = self.states.iter().filter(|s| !s.allow).filter_map(|s| {
+            s.schedule.clone().map(|schedule| (schedule, build_rules(std::iter::once((s.index, &s.items)))))
+        }).collect::<Vec<_>>();
+        let mut allow = build_rules(self.states.iter().filter(|s| s.allow).map(|s| (s.index, &s.items)));
+        self.allow_domains.iter().for_each(|domain| {
+            allow.apply(domain.clone().into(), 0);
+        });
+        debug!("filter init done, trie len = {}, exact len = {}, pattern len = {}, scheduled = {}, allow len = {}",
+               block.trie.len(), block.exact.len(), block.patterns.len(), scheduled.len(),
+               allow.trie.len() + allow.exact.len() + allow.patterns.len() + block.exceptions.len());
+        Filter::create(block, scheduled, allow)
+    }
+
+    //刷新其中一个源, 有变化就用所有源的规则重新生成Filter
+    async fn refresh(&mut self, index: usize) -> Option<Filter> {
+        let state = &mut self.states[index];
+        match state.refresh(&self.client).await {
+            Ok(true) => {
+                info!("filter {} 有更新, 重新加载", state.source.path);
+                Some(self.build())
+            }
+            Ok(false) => {
+                debug!("filter {} 没有变化", state.source.path);
+                None
+            }
+            Err(e) => {
+                error!("刷新filter {} 出错, 继续使用旧的规则: {:?}", state.source.path, e);
+                None
+            }
+        }
+    }
+}
+
+fn start_refresh_tasks(holder: Arc<FilterHolder>, loader: FilterLoader) {
+    let durations: Vec<(usize, u64)> = loader.states.iter()
+        .map(|s| s.source.refresh_duration_h).enumerate()
+        .filter(|(_, h)| *h > 0)
+        .collect();
+    //多个源的刷新排队进行, 不会同时重新生成Filter
+    let loader = Arc::new(Mutex::new(loader));
+    for (index, hours) in durations {
+        let holder = holder.clone();
+        let loader = loader.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(hours * 60 * 60));
+            //第一次是立即返回的, 启动的时候已经加载过了
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Some(filter) = loader.lock().await.refresh(index).await {
+                    holder.replace(filter);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::refresh::{FilterLoader, SourceState, FilterHolder};
+    use crate::config::FilterSource;
+    use crate::resource::ResourceClient;
+    use std::sync::{Arc, RwLock};
+
+    #[tokio::test]
+    async fn should_replace_filter_when_refresh_given_source_changed() {
+        let path = std::env::temp_dir().join("easydns_refresh_filter.txt");
+        std::fs::write(&path, "address /a.com/#\n").unwrap();
+        let source: FilterSource = path.to_str().unwrap().into();
+        let client = ResourceClient::default();
+        let mut loader = FilterLoader {
+            states: vec![SourceState::load(&client, &source, false, 0, Some(0)).await],
+            client,
+            allow_domains: vec![],
+        };
+        let holder = FilterHolder {
+            filter: RwLock::new(Arc::new(loader.build())),
+        };
+        let old = holder.get();
+
+        let unchanged = loader.refresh(0).await;
+        std::fs::write(&path, "address /b.com/#\n").unwrap();
+        holder.replace(loader.refresh(0).await.unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(unchanged.is_none());
+        assert!(old.contain(&"a.com".into()));
+        assert!(!holder.get().contain(&"a.com".into()));
+        assert!(holder.get().contain(&"b.com".into()));
+    }
+
+    #[tokio::test]
+    async fn should_keep_old_rules_when_refresh_given_source_missing() {
+        let path = std::env::temp_dir().join("easydns_missing_filter.txt");
+        std::fs::write(&path, "address /a.com/#\n").unwrap();
+        let source: FilterSource = path.to_str().unwrap().into();
+        let client = ResourceClient::default();
+        let mut loader = FilterLoader {
+            states: vec![SourceState::load(&client, &source, false, 0, Some(0)).await],
+            client,
+            allow_domains: vec![],
+        };
+        std::fs::remove_file(&path).unwrap();
+
+        let result = loader.refresh(0).await;
+
+        assert!(result.is_none());
+        assert!(loader.build().contain(&"a.com".into()));
+    }
+
+    #[tokio::test]
+    async fn should_ignore_schedule_when_load_given_no_utc_offset() {
+        let path = std::env::temp_dir().join("easydns_schedule_filter.txt");
+        std::fs::write(&path, "address /a.com/#\n").unwrap();
+        let mut source: FilterSource = path.to_str().unwrap().into();
+        source.schedule = vec!["22:00-07:00".into()];
+        let client = ResourceClient::default();
+
+        let without_offset = SourceState::load(&client, &source, false, 0, None).await;
+        let with_offset = SourceState::load(&client, &source, false, 0, Some(480)).await;
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(without_offset.schedule.is_none());
+        assert!(with_offset.schedule.is_some());
+    }
+}
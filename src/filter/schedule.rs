@@ -0,0 +1,149 @@
+use crate::system::get_now;
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_OF_DAY: i64 = 24 * 60;
+
+//一段生效时间, 比如 "mon-fri 08:00-15:00", 不写星期就是每天, 结束时间比开始早就是跨过了零点
+#[derive(Debug, Clone, PartialEq)]
+struct TimeRange {
+    //星期一到星期天
+    days: [bool; 7],
+    start: i64,
+    end: i64,
+}
+
+impl TimeRange {
+    fn parse(value: &str) -> Option<Self> {
+        let parts: Vec<&str> = value.split_whitespace().collect();
+        let (days, time) = match parts.as_slice() {
+            [time] => ([true; 7], *time),
+            [days, time] => (parse_days(days)?, *time),
+            _ => return None,
+        };
+        let mut split = time.splitn(2, '-');
+        let start = parse_minutes(split.next()?)?;
+        let end = parse_minutes(split.next()?)?;
+        Some(TimeRange { days, start, end })
+    }
+
+    //day是星期几, 0是星期一, minutes是当天的第几分钟
+    fn contains(&self, day: usize, minutes: i64) -> bool {
+        if self.start <= self.end {
+            return self.days[day] && self.start <= minutes && minutes < self.end;
+        }
+        //跨零点的话, 零点之后的部分算前一天的
+        (self.days[day] && minutes >= self.start) || (self.days[(day + 6) % 7] && minutes < self.end)
+    }
+}
+
+//"mon-fri", "sat,sun", "mon"
+fn parse_days(value: &str) -> Option<[bool; 7]> {
+    let mut days = [false; 7];
+    for part in value.to_lowercase().split(',') {
+        let mut split = part.splitn(2, '-');
+        let start = day_index(split.next()?)?;
+        let end = match split.next() {
+            Some(end) => day_index(end)?,
+            None => start,
+        };
+        let mut day = start;
+        loop {
+            days[day] = true;
+            if day == end {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Some(days)
+}
+
+fn day_index(name: &str) -> Option<usize> {
+    DAY_NAMES.iter().position(|d| *d == name.trim())
+}
+
+//"08:30" -> 510, 允许 "24:00"
+fn parse_minutes(value: &str) -> Option<i64> {
+    let mut split = value.trim().splitn(2, ':');
+    let hour: i64 = split.next()?.parse().ok()?;
+    let minute: i64 = split.next()?.parse().ok()?;
+    if minute >= 60 || hour * 60 + minute > MINUTES_OF_DAY {
+        return None;
+    }
+    Some(hour * 60 + minute)
+}
+
+//filter的生效时间, 按本地时间判断, 任意一段时间匹配就生效
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    ranges: Vec<TimeRange>,
+    utc_offset_m: i64,
+}
+
+impl Schedule {
+    //有格式不对的就返回None
+    pub fn parse(values: &[String], utc_offset_m: i64) -> Option<Self> {
+        let ranges = values.iter().map(|v| TimeRange::parse(v)).collect::<Option<Vec<_>>>()?;
+        Some(Schedule { ranges, utc_offset_m })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active_at(get_now())
+    }
+
+    fn is_active_at(&self, timestamp_ms: u128) -> bool {
+        let local_minutes = (timestamp_ms / 60_000) as i64 + self.utc_offset_m;
+        let days = local_minutes.div_euclid(MINUTES_OF_DAY);
+        let minutes = local_minutes.rem_euclid(MINUTES_OF_DAY);
+        //1970-01-01是星期四
+        let day = (days + 3).rem_euclid(7) as usize;
+        self.ranges.iter().any(|r| r.contains(day, minutes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::schedule::Schedule;
+    use crate::system::set_time_base;
+
+    //2021-08-02 00:00:00 UTC, 星期一
+    const MONDAY: u128 = 1627862400000;
+    const HOUR: u128 = 60 * 60 * 1000;
+
+    #[test]
+    fn should_be_active_in_range_when_is_active_given_weekday_schedule() {
+        let schedule = Schedule::parse(&["mon-fri 08:00-15:00".into()], 0).unwrap();
+
+        assert!(schedule.is_active_at(MONDAY + 8 * HOUR));
+        assert!(!schedule.is_active_at(MONDAY + 15 * HOUR));
+        assert!(schedule.is_active_at(MONDAY + 4 * 24 * HOUR + 10 * HOUR));
+        assert!(!schedule.is_active_at(MONDAY + 5 * 24 * HOUR + 10 * HOUR));
+    }
+
+    #[test]
+    fn should_count_as_previous_day_when_is_active_given_range_across_midnight() {
+        let schedule = Schedule::parse(&["fri 22:00-07:00".into()], 0).unwrap();
+
+        assert!(schedule.is_active_at(MONDAY + 4 * 24 * HOUR + 23 * HOUR));
+        assert!(schedule.is_active_at(MONDAY + 5 * 24 * HOUR + 6 * HOUR));
+        assert!(!schedule.is_active_at(MONDAY + 6 * HOUR));
+        assert!(!schedule.is_active_at(MONDAY + 5 * 24 * HOUR + 23 * HOUR));
+    }
+
+    #[test]
+    fn should_use_local_time_when_is_active_given_utc_offset() {
+        let schedule = Schedule::parse(&["mon 08:00-09:00".into()], 8 * 60).unwrap();
+        set_time_base(MONDAY);
+
+        assert!(schedule.is_active());
+        assert!(!schedule.is_active_at(MONDAY + 8 * HOUR));
+    }
+
+    #[test]
+    fn should_return_none_when_parse_given_illegal_schedule() {
+        assert!(Schedule::parse(&["mon-fri".into()], 0).is_none());
+        assert!(Schedule::parse(&["someday 08:00-09:00".into()], 0).is_none());
+        assert!(Schedule::parse(&["08:00-25:00".into()], 0).is_none());
+        assert!(Schedule::parse(&["sat,sun 00:00-24:00".into(), "08:00-09:00".into()], 0).is_some());
+    }
+}
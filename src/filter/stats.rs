@@ -0,0 +1,265 @@
+use crate::config::Config;
+use crate::system::get_now;
+use dashmap::DashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+//每个窗口分成多少段, 过期的时候一段一段的丢掉
+const BUCKETS_PER_WINDOW: u128 = 12;
+
+//一段时间内的域名计数, 域名数量超过上限之后新的域名只计入总数
+struct Bucket {
+    start: u128,
+    blocked: HashMap<String, u64>,
+    allowed: HashMap<String, u64>,
+    blocked_total: u64,
+    allowed_total: u64,
+}
+
+impl Bucket {
+    fn new(start: u128) -> Self {
+        Bucket {
+            start,
+            blocked: HashMap::new(),
+            allowed: HashMap::new(),
+            blocked_total: 0,
+            allowed_total: 0,
+        }
+    }
+}
+
+fn count(map: &mut HashMap<String, u64>, domain: &str, max_domains: usize) {
+    if let Some(n) = map.get_mut(domain) {
+        *n += 1;
+    } else if map.len() < max_domains {
+        map.insert(domain.into(), 1);
+    }
+}
+
+//滑动窗口, 由多个时间段组成
+struct Window {
+    duration_ms: u128,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Window {
+    fn new(duration_ms: u128) -> Self {
+        Window {
+            duration_ms,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    fn bucket_ms(&self) -> u128 {
+        (self.duration_ms / BUCKETS_PER_WINDOW).max(1)
+    }
+
+    //丢掉过期的时间段, 返回当前的时间段
+    fn current(&mut self, now: u128) -> &mut Bucket {
+        self.expire(now);
+        let start = now - now % self.bucket_ms();
+        if self.buckets.back().map(|b| b.start) != Some(start) {
+            self.buckets.push_back(Bucket::new(start));
+        }
+        self.buckets.back_mut().unwrap()
+    }
+
+    fn expire(&mut self, now: u128) {
+        let bucket_ms = self.bucket_ms();
+        while let Some(bucket) = self.buckets.front() {
+            if bucket.start + bucket_ms + self.duration_ms > now {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    fn report(&mut self, now: u128, top_n: usize) -> WindowReport {
+        self.expire(now);
+        let mut blocked = HashMap::new();
+        let mut allowed = HashMap::new();
+        self.buckets.iter().for_each(|bucket| {
+            bucket.blocked.iter().for_each(|(d, n)| *blocked.entry(d.as_str()).or_insert(0) += n);
+            bucket.allowed.iter().for_each(|(d, n)| *allowed.entry(d.as_str()).or_insert(0) += n);
+        });
+        WindowReport {
+            duration_m: (self.duration_ms / 60_000) as u64,
+            blocked: self.buckets.iter().map(|b| b.blocked_total).sum(),
+            allowed: self.buckets.iter().map(|b| b.allowed_total).sum(),
+            top_blocked: top(blocked, top_n),
+            top_allowed: top(allowed, top_n),
+        }
+    }
+}
+
+//按次数从大到小, 一样的按域名排序
+fn top(map: HashMap<&str, u64>, top_n: usize) -> Vec<(String, u64)> {
+    let mut vec: Vec<(String, u64)> = map.into_iter().map(|(d, n)| (d.into(), n)).collect();
+    vec.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    vec.truncate(top_n);
+    vec
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowReport {
+    pub duration_m: u64,
+    pub blocked: u64,
+    pub allowed: u64,
+    pub top_blocked: Vec<(String, u64)>,
+    pub top_allowed: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsReport {
+    //每个filter文件拦截的次数, 从启动开始算
+    pub lists: Vec<(String, u64)>,
+    pub windows: Vec<WindowReport>,
+}
+
+impl Display for StatsReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "lists: {:?}", self.lists)?;
+        for w in self.windows.iter() {
+            write!(f, "\nlast {}m: blocked {}, allowed {}, top blocked: {:?}, top allowed: {:?}",
+                   w.duration_m, w.blocked, w.allowed, w.top_blocked, w.top_allowed)?;
+        }
+        Ok(())
+    }
+}
+
+//拦截和放行的统计, 都在内存里面, 占用的大小有上限
+pub struct FilterStats {
+    lists: DashMap<String, u64>,
+    windows: Mutex<Vec<Window>>,
+    max_domains: usize,
+    top_n: usize,
+}
+
+impl FilterStats {
+    pub fn from(config: &Config) -> Arc<Self> {
+        let stats = Arc::new(FilterStats {
+            lists: DashMap::new(),
+            windows: Mutex::new(config.stats_windows_m.iter()
+                .map(|m| Window::new(*m as u128 * 60_000)).collect()),
+            max_domains: config.stats_max_domains,
+            top_n: config.stats_top_n,
+        });
+        if config.stats_report_duration_m > 0 {
+            start_report_task(stats.clone(), config.stats_report_duration_m as u64);
+        }
+        stats
+    }
+
+    //list是拦截这个域名的filter文件
+    pub fn record_block(&self, list: &str, domain: &str) {
+        *self.lists.entry(list.into()).or_insert(0) += 1;
+        let now = get_now();
+        self.windows.lock().unwrap().iter_mut().for_each(|w| {
+            let bucket = w.current(now);
+            bucket.blocked_total += 1;
+            count(&mut bucket.blocked, domain, self.max_domains);
+        });
+    }
+
+    pub fn record_allow(&self, domain: &str) {
+        let now = get_now();
+        self.windows.lock().unwrap().iter_mut().for_each(|w| {
+            let bucket = w.current(now);
+            bucket.allowed_total += 1;
+            count(&mut bucket.allowed, domain, self.max_domains);
+        });
+    }
+
+    pub fn report(&self) -> StatsReport {
+        let now = get_now();
+        let mut lists: Vec<(String, u64)> = self.lists.iter()
+            .map(|e| (e.key().clone(), *e.value())).collect();
+        lists.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let windows = self.windows.lock().unwrap().iter_mut()
+            .map(|w| w.report(now, self.top_n)).collect();
+        StatsReport {
+            lists,
+            windows,
+        }
+    }
+}
+
+fn start_report_task(stats: Arc<FilterStats>, minutes: u64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(minutes * 60));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            info!("filter stats:\n{}", stats.report());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::stats::{FilterStats, Window};
+    use crate::system::set_time_base;
+    use dashmap::DashMap;
+    use std::sync::Mutex;
+
+    const MINUTE: u128 = 60_000;
+
+    fn stats(windows_m: Vec<u128>, max_domains: usize) -> FilterStats {
+        FilterStats {
+            lists: DashMap::new(),
+            windows: Mutex::new(windows_m.into_iter().map(|m| Window::new(m * MINUTE)).collect()),
+            max_domains,
+            top_n: 2,
+        }
+    }
+
+    #[test]
+    fn should_return_counts_and_top_domains_when_report_given_records() {
+        let stats = stats(vec![60], 100);
+        set_time_base(0);
+        stats.record_block("ads.txt", "a.com");
+        stats.record_block("ads.txt", "a.com");
+        stats.record_block("track.txt", "b.com");
+        stats.record_block("track.txt", "c.com");
+        stats.record_allow("good.com");
+
+        let report = stats.report();
+
+        assert_eq!(vec![("ads.txt".to_string(), 2), ("track.txt".to_string(), 2)], report.lists);
+        let window = &report.windows[0];
+        assert_eq!((4, 1), (window.blocked, window.allowed));
+        assert_eq!(vec![("a.com".to_string(), 2), ("b.com".to_string(), 1)], window.top_blocked);
+        assert_eq!(vec![("good.com".to_string(), 1)], window.top_allowed);
+    }
+
+    #[test]
+    fn should_drop_old_records_when_report_given_window_passed() {
+        let stats = stats(vec![10, 60], 100);
+        set_time_base(0);
+        stats.record_block("ads.txt", "a.com");
+        set_time_base(30 * MINUTE);
+        stats.record_block("ads.txt", "b.com");
+
+        let report = stats.report();
+
+        assert_eq!(vec![("b.com".to_string(), 1)], report.windows[0].top_blocked);
+        assert_eq!(2, report.windows[1].blocked);
+        assert_eq!(vec![("ads.txt".to_string(), 2)], report.lists);
+    }
+
+    #[test]
+    fn should_only_count_total_when_record_given_too_many_domains() {
+        let stats = stats(vec![60], 1);
+        set_time_base(0);
+        stats.record_allow("a.com");
+        stats.record_allow("b.com");
+        stats.record_allow("a.com");
+
+        let report = stats.report();
+
+        assert_eq!(3, report.windows[0].allowed);
+        assert_eq!(vec![("a.com".to_string(), 2)], report.windows[0].top_allowed);
+    }
+}
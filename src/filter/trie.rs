@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+//按照域名的label倒序存储, com -> baidu -> www, 查询的时候不需要分配内存
+pub struct DomainTrie<V> {
+    root: Node<V>,
+    len: usize,
+}
+
+struct Node<V> {
+    children: HashMap<Box<str>, Node<V>>,
+    value: Option<V>,
+}
+
+impl<V> Node<V> {
+    fn new() -> Self {
+        Node {
+            children: HashMap::new(),
+            value: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.is_empty()
+    }
+
+    //返回删除掉的值, 子节点空了就顺便删掉
+    fn remove<'a>(&mut self, mut labels: impl Iterator<Item=&'a str>) -> Option<V> {
+        match labels.next() {
+            None => self.value.take(),
+            Some(label) => {
+                let child = self.children.get_mut(label)?;
+                let value = child.remove(labels);
+                if child.is_empty() {
+                    self.children.remove(label);
+                }
+                value
+            }
+        }
+    }
+}
+
+impl<V> DomainTrie<V> {
+    pub fn new() -> Self {
+        DomainTrie {
+            root: Node::new(),
+            len: 0,
+        }
+    }
+
+    pub fn insert(&mut self, domain: &str, value: V) -> Option<V> {
+        let mut node = &mut self.root;
+        for label in domain.rsplit('.') {
+            node = node.children.entry(label.into()).or_insert_with(Node::new);
+        }
+        let old = node.value.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove(&mut self, domain: &str) -> Option<V> {
+        let old = self.root.remove(domain.rsplit('.'));
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    //精确匹配
+    pub fn get(&self, domain: &str) -> Option<&V> {
+        let mut node = &self.root;
+        for label in domain.rsplit('.') {
+            node = node.children.get(label)?;
+        }
+        node.value.as_ref()
+    }
+
+    //匹配域名本身或者它的上级域名, 返回最具体(label最多)的那个
+    pub fn find(&self, domain: &str) -> Option<&V> {
+        self.find_with_depth(domain).map(|(_, v)| v)
+    }
+
+    //depth是匹配到的规则的label数量
+    pub fn find_with_depth(&self, domain: &str) -> Option<(usize, &V)> {
+        let mut node = &self.root;
+        let mut result = None;
+        for (index, label) in domain.rsplit('.').enumerate() {
+            node = match node.children.get(label) {
+                Some(child) => child,
+                None => break,
+            };
+            if let Some(value) = &node.value {
+                result = Some((index + 1, value));
+            }
+        }
+        result
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::trie::DomainTrie;
+
+    #[test]
+    fn should_return_true_when_call_find_given_sub_domain_of_item() {
+        let trie = get_trie();
+
+        let result = trie.find("www.baidu.com");
+
+        assert_eq!(Some(&1), result)
+    }
+
+    #[test]
+    fn should_return_most_specific_when_call_find_given_nested_items() {
+        let mut trie = get_trie();
+        trie.insert("www.baidu.com", 2);
+
+        let result = trie.find_with_depth("a.www.baidu.com");
+
+        assert_eq!(Some((3, &2)), result)
+    }
+
+    #[test]
+    fn should_return_none_when_call_find_given_only_same_suffix_string() {
+        let trie = get_trie();
+
+        let result = trie.find("xbaidu.com");
+
+        assert_eq!(None, result)
+    }
+
+    #[test]
+    fn should_return_none_when_call_get_given_sub_domain_of_item() {
+        let trie = get_trie();
+
+        let result = trie.get("www.baidu.com");
+
+        assert_eq!(None, result)
+    }
+
+    #[test]
+    fn should_remove_item_and_empty_nodes_when_call_remove_given_exist_item() {
+        let mut trie = get_trie();
+
+        let result = trie.remove("baidu.com");
+
+        assert_eq!(Some(1), result);
+        assert!(trie.is_empty());
+        assert!(trie.root.children.is_empty())
+    }
+
+    #[test]
+    fn should_keep_len_when_call_insert_given_exist_item() {
+        let mut trie = get_trie();
+
+        trie.insert("baidu.com", 3);
+
+        assert_eq!(1, trie.len());
+        assert_eq!(Some(&3), trie.get("baidu.com"))
+    }
+
+    fn get_trie() -> DomainTrie<i32> {
+        let mut trie = DomainTrie::new();
+        trie.insert("baidu.com", 1);
+        trie
+    }
+}
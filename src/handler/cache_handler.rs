@@ -23,9 +23,15 @@ impl CacheHandler {
 #[async_trait]
 impl Handler for CacheHandler {
     async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        //缓存按域名存的是A查询的应答, 其他类型的查询不能用
+        if query.get_type() != 1 {
+            return clain.next(query).await;
+        }
         let id = query.get_id().clone();
+        let name = query.get_name().clone();
+        let subnet = query.get_client_subnet().cloned();
         self.cache_pool
-            .get(query.get_name().clone(), clain.next(query).boxed()).await
+            .get(name, subnet.as_ref(), clain.next(query).boxed()).await
             .map(|mut r| {
                 r.set_id(id);
                 r
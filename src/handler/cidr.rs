@@ -0,0 +1,56 @@
+use std::net::IpAddr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    //"1.2.3.4" 或者 "1.2.3.0/24", 格式不对返回None
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut split = value.trim().splitn(2, '/');
+        let network: IpAddr = split.next()?.parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match split.next() {
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|p| *p <= max)?,
+            None => max,
+        };
+        Some(Cidr { network, prefix })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (&self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::cidr::Cidr;
+
+    #[test]
+    fn should_match_ip_in_range_when_contains_given_cidr() {
+        let cidr = Cidr::parse("10.1.0.0/16").unwrap();
+        let single = Cidr::parse("1.2.3.4").unwrap();
+
+        assert!(cidr.contains(&"10.1.255.1".parse().unwrap()));
+        assert!(!cidr.contains(&"10.2.0.1".parse().unwrap()));
+        assert!(single.contains(&"1.2.3.4".parse().unwrap()));
+        assert!(!single.contains(&"1.2.3.5".parse().unwrap()));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(&"8.8.8.8".parse().unwrap()));
+        assert!(Cidr::parse("2001:db8::/32").unwrap().contains(&"2001:db8::1".parse().unwrap()));
+        assert!(!Cidr::parse("2001:db8::/32").unwrap().contains(&"10.1.0.1".parse().unwrap()));
+        assert!(Cidr::parse("10.0.0.0/33").is_none());
+        assert!(Cidr::parse("abc").is_none());
+    }
+}
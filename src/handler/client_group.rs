@@ -0,0 +1,219 @@
+use crate::config::{Config, FilterPolicy};
+use crate::filter::FilterHolder;
+use crate::handler::cidr::Cidr;
+use crate::handler::domain_filter::BlockResponse;
+use crate::resource::ResourceClient;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+//一个分组实际使用的过滤规则和拦截方式
+#[derive(Clone)]
+pub struct Policy {
+    pub filter: Arc<FilterHolder>,
+    pub response: Arc<BlockResponse>,
+    pub safe_search: bool,
+    //filters的路径, 统计的时候用
+    pub lists: Arc<Vec<String>>,
+}
+
+impl Policy {
+    pub fn list_name(&self, source: usize) -> &str {
+        self.lists.get(source).map(|s| s.as_str()).unwrap_or("")
+    }
+
+    fn from(policy: &FilterPolicy, config: &Config, filter: Arc<FilterHolder>) -> Self {
+        Policy {
+            filter,
+            response: Arc::new(BlockResponse::from(policy, config.block_ttl)),
+            safe_search: policy.safe_search,
+            lists: Arc::new(policy.filters.iter().map(|f| f.path.clone()).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClientMatcher {
+    Cidr(Cidr),
+    //mac地址, 匹配用它生成的ipv6地址
+    Mac([u8; 6]),
+}
+
+impl ClientMatcher {
+    fn parse(value: &str) -> Option<Self> {
+        parse_mac(value).map(|mac| ClientMatcher::Mac(mac))
+            .or_else(|| Cidr::parse(value).map(|cidr| ClientMatcher::Cidr(cidr)))
+    }
+
+    fn matches(&self, client: &IpAddr) -> bool {
+        match (self, client) {
+            (ClientMatcher::Cidr(cidr), _) => cidr.contains(client),
+            //EUI-64: mac中间插入ff:fe, 第一个字节的U/L位取反, 作为ipv6地址的后64位
+            (ClientMatcher::Mac(mac), IpAddr::V6(ip)) => {
+                let octets = ip.octets();
+                let eui64 = [mac[0] ^ 0x02, mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]];
+                octets[8..] == eui64
+            }
+            _ => false,
+        }
+    }
+}
+
+//aa:bb:cc:dd:ee:ff 或者 aa-bb-cc-dd-ee-ff
+fn parse_mac(value: &str) -> Option<[u8; 6]> {
+    let parts: Vec<&str> = value.trim().split(|c| c == ':' || c == '-').collect();
+    if parts.len() != 6 || parts.iter().any(|p| p.len() != 2) {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (i, part) in parts.iter().enumerate() {
+        mac[i] = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some(mac)
+}
+
+//按客户端地址选择过滤规则, 都不匹配的用全局的规则
+pub struct ClientPolicies {
+    default: Policy,
+    groups: Vec<(Vec<ClientMatcher>, Policy)>,
+}
+
+impl ClientPolicies {
+    pub async fn from(config: &Config, client: ResourceClient) -> Self {
+        let mut holders = Vec::new();
+        let filter = shared_holder(&mut holders, &config.policy, config, &client).await;
+        let default = Policy::from(&config.policy, config, filter);
+        let mut groups = Vec::with_capacity(config.client_groups.len());
+        for group in config.client_groups.iter() {
+            let matchers: Vec<ClientMatcher> = group.clients.iter().filter_map(|value| {
+                let matcher = ClientMatcher::parse(value);
+                if matcher.is_none() {
+                    error!("客户端分组{}里面的地址格式不对: {}", group.name, value);
+                }
+                matcher
+            }).collect();
+            let filter = shared_holder(&mut holders, &group.policy, config, &client).await;
+            let policy = Policy::from(&group.policy, config, filter);
+            groups.push((matchers, policy));
+        }
+        ClientPolicies {
+            default,
+            groups,
+        }
+    }
+
+    pub fn default(&self) -> &Policy {
+        &self.default
+    }
+
+    pub fn get(&self, client: Option<&IpAddr>) -> &Policy {
+        let client = match client {
+            Some(client) => normalize(client),
+            None => return &self.default,
+        };
+        self.groups.iter()
+            .find(|(matchers, _)| matchers.iter().any(|m| m.matches(&client)))
+            .map(|(_, policy)| policy)
+            .unwrap_or(&self.default)
+    }
+}
+
+//filters, allowlists和allow_domains都一样的分组共用一份规则, 不用重复下载, 解析和刷新
+async fn shared_holder<'a>(holders: &mut Vec<(&'a FilterPolicy, Arc<FilterHolder>)>, policy: &'a FilterPolicy,
+                           config: &Config, client: &ResourceClient) -> Arc<FilterHolder> {
+    let found = holders.iter().find(|(p, _)| {
+        p.filters == policy.filters && p.allowlists == policy.allowlists && p.allow_domains == policy.allow_domains
+    });
+    if let Some((_, holder)) = found {
+        return holder.clone();
+    }
+    let holder = FilterHolder::from(policy, config.utc_offset_m, client.clone()).await;
+    holders.push((policy, holder.clone()));
+    holder
+}
+
+//ipv4映射的ipv6地址(::ffff:1.2.3.4)按ipv4匹配
+fn normalize(client: &IpAddr) -> IpAddr {
+    match client {
+        IpAddr::V6(ip) => match ip.segments() {
+            [0, 0, 0, 0, 0, 0xffff, _, _] => IpAddr::V4(ip.to_ipv4().unwrap()),
+            _ => *client,
+        },
+        _ => *client,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::handler::client_group::{ClientMatcher, ClientPolicies, normalize};
+    use crate::resource::ResourceClient;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use toml::Value;
+
+    #[test]
+    fn should_return_matcher_when_parse_given_ip_cidr_and_mac() {
+        assert!(matches!(ClientMatcher::parse("192.168.1.10"), Some(ClientMatcher::Cidr(_))));
+        assert!(matches!(ClientMatcher::parse("192.168.1.0/24"), Some(ClientMatcher::Cidr(_))));
+        assert_eq!(Some(ClientMatcher::Mac([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff])),
+                   ClientMatcher::parse("AA-BB-CC-DD-EE-FF"));
+        assert_eq!(None, ClientMatcher::parse("aa:bb:cc:dd:ee"));
+        assert_eq!(None, ClientMatcher::parse("kids"));
+    }
+
+    #[test]
+    fn should_match_client_when_matches_given_cidr_matcher() {
+        let matcher = ClientMatcher::parse("192.168.1.0/24").unwrap();
+
+        assert!(matcher.matches(&"192.168.1.20".parse().unwrap()));
+        assert!(!matcher.matches(&"192.168.2.20".parse().unwrap()));
+    }
+
+    #[test]
+    fn should_match_eui64_address_when_matches_given_mac_matcher() {
+        let matcher = ClientMatcher::parse("00:11:22:33:44:55").unwrap();
+
+        assert!(matcher.matches(&"fe80::211:22ff:fe33:4455".parse().unwrap()));
+        assert!(matcher.matches(&"2408:8000:1::211:22ff:fe33:4455".parse().unwrap()));
+        assert!(!matcher.matches(&"fe80::211:22ff:fe33:4456".parse().unwrap()));
+        assert!(!matcher.matches(&"192.168.1.20".parse().unwrap()));
+    }
+
+    #[test]
+    fn should_return_ipv4_when_normalize_given_ipv4_mapped_address() {
+        let mapped: IpAddr = "::ffff:192.168.1.20".parse().unwrap();
+
+        assert_eq!("192.168.1.20".parse::<IpAddr>().unwrap(), normalize(&mapped));
+    }
+
+    #[tokio::test]
+    async fn should_share_filter_holder_when_from_given_groups_with_same_lists() {
+        let config = Config::from(r#"
+            cache-file = "cache"
+            cache = false
+            cache-num = 100
+            port = 0
+            servers = []
+            log-level = "error"
+            ip-choose-strategy = 0
+            cache-get-strategy = 0
+            cache-ttl-timeout-ms = 0
+            server-choose-strategy = 1
+            server-choose-duration-h = 0
+            filters = []
+            client-groups = [
+                { name = "kids", clients = ["192.168.1.10"], safe-search = true },
+                { name = "guest", clients = ["192.168.1.20"], block-mode = "nxdomain" },
+                { name = "work", clients = ["192.168.1.30"], allow-domains = ["example.com"] },
+            ]
+        "#.parse::<Value>().unwrap());
+        let client = ResourceClient::from(&config).await.unwrap();
+
+        let policies = ClientPolicies::from(&config, client).await;
+
+        let filter = |ip: &str| policies.get(Some(&ip.parse().unwrap())).filter.clone();
+        assert!(Arc::ptr_eq(&filter("192.168.1.10"), &filter("192.168.1.20")));
+        assert!(Arc::ptr_eq(&policies.default().filter, &filter("192.168.1.10")));
+        assert!(!Arc::ptr_eq(&filter("192.168.1.10"), &filter("192.168.1.30")));
+    }
+}
@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use crate::filter::FilterStats;
+use crate::handler::client_group::ClientPolicies;
+use std::sync::Arc;
+use crate::handler::{Clain, Handler};
+use crate::system::Result;
+use crate::protocol::{DnsAnswer, DnsQuery};
+
+//应答的cname链上有被拦截的域名, 整个应答都拦截掉, 在缓存前面处理, 缓存里面存的是没有拦截的应答
+//每个客户端组的规则不一样, 从缓存取出来的应答也要检查
+#[derive(Clone)]
+pub struct CnameFilter {
+    policies: Arc<ClientPolicies>,
+    stats: Arc<FilterStats>,
+}
+
+impl CnameFilter {
+    pub fn new(policies: Arc<ClientPolicies>, stats: Arc<FilterStats>) -> Self {
+        CnameFilter {
+            policies,
+            stats,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for CnameFilter {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let answer = clain.next(query.clone()).await?;
+        let policy = self.policies.get(query.get_client());
+        let filter = policy.filter.get();
+        let blocked = answer.get_cnames().iter().find_map(|cname| {
+            filter.find(cname).map(|source| (cname, source))
+        });
+        if let Some((cname, source)) = blocked {
+            debug!("{} 的cname {} 被拦截", query.get_name(), cname);
+            self.stats.record_block(policy.list_name(source), query.get_name());
+            return Ok(policy.response.answer(source, &query));
+        }
+        Ok(answer)
+    }
+}
@@ -0,0 +1,199 @@
+use crate::config::Config;
+use crate::handler::local_records::LocalRecordHolder;
+use crate::resource::ResourceClient;
+use crate::system::{Result, get_now};
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+//udhcpd的租约文件是二进制的, 开头是8字节的写入时间, 后面每条租约36字节
+const UDHCPD_HEADER_LEN: usize = 8;
+const UDHCPD_LEASE_LEN: usize = 36;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LeaseFormat {
+    Dnsmasq,
+    Udhcpd,
+}
+
+impl LeaseFormat {
+    fn from(format: &str) -> Self {
+        match format {
+            "udhcpd" => LeaseFormat::Udhcpd,
+            _ => LeaseFormat::Dnsmasq,
+        }
+    }
+}
+
+//dnsmasq的格式是一行一条: 过期时间 mac ip 主机名 client-id, 过期时间是0代表永不过期, 主机名未知是*
+//ipv6的租约在duid那一行后面, 格式一样, 只是mac换成了iaid
+fn parse_dnsmasq(text: &str, now_s: u64) -> Vec<(String, IpAddr)> {
+    text.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 || fields[0] == "duid" {
+            return None;
+        }
+        let expiry = fields[0].parse::<u64>().ok()?;
+        if expiry != 0 && expiry <= now_s {
+            return None;
+        }
+        let ip = fields[2].parse::<IpAddr>().ok()?;
+        Some((fields[3].to_string(), ip))
+    }).collect()
+}
+
+//每条租约: 剩余秒数(4字节) ip(4字节) mac(6字节) 主机名(20字节, 0结尾) 填充(2字节), 数字都是网络字节序
+fn parse_udhcpd(bytes: &[u8], now_s: u64) -> Vec<(String, IpAddr)> {
+    if bytes.len() < UDHCPD_HEADER_LEN {
+        return vec![];
+    }
+    let written_at = i64::from_be_bytes(bytes[..UDHCPD_HEADER_LEN].try_into().unwrap()).max(0) as u64;
+    bytes[UDHCPD_HEADER_LEN..].chunks_exact(UDHCPD_LEASE_LEN).filter_map(|lease| {
+        let remaining = u32::from_be_bytes(lease[0..4].try_into().unwrap()) as u64;
+        if written_at + remaining <= now_s {
+            return None;
+        }
+        let ip = Ipv4Addr::new(lease[4], lease[5], lease[6], lease[7]);
+        let hostname = &lease[14..34];
+        let end = hostname.iter().position(|b| *b == 0).unwrap_or(hostname.len());
+        let hostname = String::from_utf8_lossy(&hostname[..end]).to_string();
+        Some((hostname, IpAddr::V4(ip)))
+    }).collect()
+}
+
+//主机名加上域名后缀, 没有主机名或者主机名不合法的租约不发布
+fn lease_names(leases: Vec<(String, IpAddr)>, domain: &str) -> Vec<(String, IpAddr)> {
+    let mut names: Vec<(String, IpAddr)> = leases.into_iter().filter_map(|(hostname, ip)| {
+        let hostname = hostname.to_lowercase();
+        let valid = !hostname.is_empty() && !hostname.starts_with('-')
+            && hostname.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            if hostname != "*" && !hostname.is_empty() {
+                debug!("dhcp租约的主机名不合法, 忽略: {} {}", hostname, ip);
+            }
+            return None;
+        }
+        let name = if domain.is_empty() { hostname } else { format!("{}.{}", hostname, domain) };
+        Some((name, ip))
+    }).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+struct LeaseWatcher {
+    holder: Arc<LocalRecordHolder>,
+    client: ResourceClient,
+    path: String,
+    format: LeaseFormat,
+    domain: String,
+    ttl: u32,
+    current: Vec<(String, IpAddr)>,
+}
+
+impl LeaseWatcher {
+    async fn read(&self) -> Result<Vec<(String, IpAddr)>> {
+        let mut bytes = Vec::new();
+        let mut reader = self.client.open(&self.path).await?;
+        reader.read_to_end(&mut bytes).await?;
+        let now_s = (get_now() / 1000) as u64;
+        let leases = match self.format {
+            LeaseFormat::Dnsmasq => parse_dnsmasq(&String::from_utf8_lossy(&bytes), now_s),
+            LeaseFormat::Udhcpd => parse_udhcpd(&bytes, now_s),
+        };
+        Ok(lease_names(leases, &self.domain))
+    }
+
+    //租约有变化的话替换本地记录, 读取出错就继续用旧的租约
+    async fn refresh(&mut self) {
+        match self.read().await {
+            Ok(names) if names != self.current => {
+                info!("dhcp租约有变化, 现在有{}个主机名", names.len());
+                self.holder.set_leases(names.clone(), self.ttl);
+                self.current = names;
+            }
+            Ok(_) => {}
+            Err(e) => error!("读取dhcp租约文件{}出错: {:?}", self.path, e),
+        }
+    }
+}
+
+//启动的时候先读一次, 然后定时检查租约文件
+pub async fn watch_leases(holder: Arc<LocalRecordHolder>, client: ResourceClient, config: &Config) {
+    let mut watcher = LeaseWatcher {
+        holder,
+        client,
+        path: config.dhcp_lease_file.clone(),
+        format: LeaseFormat::from(config.dhcp_lease_format.as_str()),
+        domain: config.dhcp_domain.trim_matches('.').to_lowercase(),
+        ttl: config.dhcp_ttl as u32,
+        current: vec![],
+    };
+    watcher.refresh().await;
+    let seconds = config.dhcp_lease_check_duration_s as u64;
+    if seconds == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            watcher.refresh().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::dhcp_lease::{parse_dnsmasq, parse_udhcpd, lease_names};
+    use std::net::IpAddr;
+
+    #[test]
+    fn should_return_active_leases_when_parse_dnsmasq_given_lease_file() {
+        let text = "1700000100 aa:bb:cc:dd:ee:01 192.168.1.10 laptop 01:aa:bb:cc:dd:ee:01\n\
+                    1600000000 aa:bb:cc:dd:ee:02 192.168.1.11 old-phone *\n\
+                    0 aa:bb:cc:dd:ee:03 192.168.1.12 * *\n\
+                    duid 00:01:00:01:aa:bb:cc:dd\n\
+                    1700000100 1234 fd00::10 laptop 00:01:00:01\n";
+
+        let leases = parse_dnsmasq(text, 1700000000);
+
+        assert_eq!(vec![("laptop".to_string(), "192.168.1.10".parse::<IpAddr>().unwrap()),
+                        ("*".to_string(), "192.168.1.12".parse().unwrap()),
+                        ("laptop".to_string(), "fd00::10".parse().unwrap())], leases);
+    }
+
+    #[test]
+    fn should_return_active_leases_when_parse_udhcpd_given_lease_file() {
+        let lease = |remaining: u32, ip: [u8; 4], hostname: &str| {
+            let mut bytes = remaining.to_be_bytes().to_vec();
+            bytes.extend(&ip);
+            bytes.extend(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0x01]);
+            let mut name = [0u8; 20];
+            name[..hostname.len()].copy_from_slice(hostname.as_bytes());
+            bytes.extend(&name);
+            bytes.extend(&[0, 0]);
+            bytes
+        };
+        let mut bytes = 1700000000i64.to_be_bytes().to_vec();
+        bytes.extend(lease(3600, [192, 168, 1, 20], "printer"));
+        bytes.extend(lease(60, [192, 168, 1, 21], "tv"));
+
+        let leases = parse_udhcpd(&bytes, 1700000100);
+
+        assert_eq!(vec![("printer".to_string(), "192.168.1.20".parse::<IpAddr>().unwrap())], leases);
+    }
+
+    #[test]
+    fn should_add_domain_and_skip_invalid_when_lease_names_given_hostnames() {
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+        let leases = vec![("Laptop".to_string(), ip), ("*".to_string(), ip), ("bad_name".to_string(), ip),
+                          ("laptop".to_string(), ip)];
+
+        assert_eq!(vec![("laptop.lan".to_string(), ip)], lease_names(leases.clone(), "lan"));
+        assert_eq!(vec![("laptop".to_string(), ip)], lease_names(leases, ""));
+    }
+}
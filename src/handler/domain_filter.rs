@@ -1,19 +1,24 @@
 use async_trait::async_trait;
-use crate::filter::Filter;
+use crate::config::FilterPolicy;
+use crate::filter::FilterStats;
+use crate::handler::client_group::ClientPolicies;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::Arc;
 use crate::handler::{Clain, Handler};
 use crate::system::Result;
-use crate::protocol::{DnsAnswer, SoaAnswer, DnsQuery};
+use crate::protocol::{DnsAnswer, DnsQuery, LocalAnswer, LocalRecord};
 
 #[derive(Clone)]
 pub struct DomainFilter {
-    filter: Arc<Filter>,
+    policies: Arc<ClientPolicies>,
+    stats: Arc<FilterStats>,
 }
 
 impl DomainFilter {
-    pub fn new(filter: Arc<Filter>) -> Self {
+    pub fn new(policies: Arc<ClientPolicies>, stats: Arc<FilterStats>) -> Self {
         DomainFilter {
-            filter
+            policies,
+            stats,
         }
     }
 }
@@ -21,12 +26,151 @@ impl DomainFilter {
 #[async_trait]
 impl Handler for DomainFilter {
     async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
-        let domain = query.get_name().clone();
-        if self.filter.contain(&domain) {
-            //返回soa
-            return Ok(DnsAnswer::from(SoaAnswer::default_soa(
-                query.get_id().clone(), domain)));
+        let policy = self.policies.get(query.get_client());
+        if let Some(source) = policy.filter.get().find(query.get_name()) {
+            self.stats.record_block(policy.list_name(source), query.get_name());
+            return Ok(policy.response.answer(source, &query));
         }
-        clain.next(query).await
+        let name = query.get_name().clone();
+        let answer = clain.next(query).await?;
+        //cname被拦截的已经在CnameFilter里面统计过了
+        if !answer.is_blocked() {
+            self.stats.record_allow(&name);
+        }
+        Ok(answer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlockMode {
+    //NODATA, 带一个soa记录
+    Soa,
+    NxDomain,
+    Refused,
+    //A返回0.0.0.0, AAAA返回::
+    Zero,
+    Ip(IpAddr),
+}
+
+impl From<&str> for BlockMode {
+    fn from(mode: &str) -> Self {
+        match mode.to_lowercase().as_str() {
+            "soa" => BlockMode::Soa,
+            "nxdomain" => BlockMode::NxDomain,
+            "refused" => BlockMode::Refused,
+            "zero" => BlockMode::Zero,
+            other => match other.parse::<IpAddr>() {
+                Ok(ip) if ip.is_unspecified() => BlockMode::Zero,
+                Ok(ip) => BlockMode::Ip(ip),
+                Err(_) => {
+                    error!("不支持的block-mode: {}, 将会返回soa", mode);
+                    BlockMode::Soa
+                }
+            }
+        }
+    }
+}
+
+//被拦截的域名的回答方式, 每个filter可以单独设置
+pub struct BlockResponse {
+    modes: Vec<BlockMode>,
+    ttl: u32,
+}
+
+impl BlockResponse {
+    pub fn from(policy: &FilterPolicy, ttl: usize) -> Self {
+        let default = BlockMode::from(policy.block_mode.as_str());
+        let modes = policy.filters.iter().map(|source| {
+            if source.block_mode.is_empty() {
+                default.clone()
+            } else {
+                BlockMode::from(source.block_mode.as_str())
+            }
+        }).collect();
+        BlockResponse {
+            modes,
+            ttl: ttl as u32,
+        }
+    }
+
+    //source是拦截这个域名的filter的序号
+    pub fn answer(&self, source: usize, query: &DnsQuery) -> DnsAnswer {
+        let mode = self.modes.get(source).unwrap_or(&BlockMode::Soa);
+        let name = query.get_name().clone();
+        let ip = match (mode, query.get_type()) {
+            (BlockMode::Zero, 1) => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            (BlockMode::Zero, 28) => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            (BlockMode::Ip(ip @ IpAddr::V4(_)), 1) => Some(*ip),
+            (BlockMode::Ip(ip @ IpAddr::V6(_)), 28) => Some(*ip),
+            _ => None,
+        };
+        let mut answer = match (mode, ip) {
+            (BlockMode::Refused, _) => LocalAnswer::new(query, 5),
+            (BlockMode::NxDomain, _) => {
+                let mut answer = LocalAnswer::new(query, 3);
+                answer.add_authority(LocalRecord::soa(name, self.ttl));
+                answer
+            }
+            (_, Some(ip)) => {
+                let mut answer = LocalAnswer::new(query, 0);
+                answer.add_answer(LocalRecord::ip(name, self.ttl, &ip));
+                answer
+            }
+            //查询类型和ip类型不一样的时候也是返回soa
+            (_, None) => {
+                let mut answer = LocalAnswer::new(query, 0);
+                answer.add_authority(LocalRecord::soa(name, self.ttl));
+                answer
+            }
+        };
+        answer.set_blocked();
+        answer.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::domain_filter::{BlockMode, BlockResponse};
+    use crate::protocol::{DnsQuery, LocalAnswer};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_return_mode_when_from_given_mode_strings() {
+        assert_eq!(BlockMode::NxDomain, BlockMode::from("NXDOMAIN"));
+        assert_eq!(BlockMode::Zero, BlockMode::from("0.0.0.0"));
+        assert_eq!(BlockMode::Zero, BlockMode::from("::"));
+        assert_eq!(BlockMode::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))), BlockMode::from("10.0.0.1"));
+        assert_eq!(BlockMode::Soa, BlockMode::from("unknown"));
+    }
+
+    #[test]
+    fn should_return_answer_of_source_mode_when_answer_given_per_filter_modes() {
+        let response = BlockResponse {
+            modes: vec![BlockMode::Soa, BlockMode::NxDomain, BlockMode::Refused,
+                        BlockMode::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))],
+            ttl: 60,
+        };
+        let query = DnsQuery::from("ads.com");
+
+        let results: Vec<(u16, usize)> = (0..4).map(|source| {
+            let answer = response.answer(source, &query);
+            let local = answer.as_any().downcast_ref::<LocalAnswer>().unwrap();
+            (local.get_rcode(), local.get_answers().len())
+        }).collect();
+
+        assert_eq!(vec![(0, 0), (3, 0), (5, 0), (0, 1)], results);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_return_soa_when_answer_given_ip_mode_and_other_query_type() {
+        let response = BlockResponse {
+            modes: vec![BlockMode::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)))],
+            ttl: 60,
+        };
+
+        let answer = response.answer(0, &DnsQuery::with_type("ads.com", 28));
+
+        let local = answer.as_any().downcast_ref::<LocalAnswer>().unwrap();
+        assert!(local.get_answers().is_empty());
+    }
+}
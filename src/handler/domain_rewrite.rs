@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use crate::cache::CachePool;
+use crate::config::RewriteConfig;
+use crate::handler::{Clain, Handler, Resolver, cname_answer};
+use crate::system::Result;
+use crate::protocol::{DnsAnswer, DnsQuery, Ipv4Answer};
+use futures_util::FutureExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+//改写规则, 精确的域名在map里面, *.后缀的按后缀从长到短排列
+pub struct RewriteRules {
+    exact: HashMap<String, String>,
+    suffixes: Vec<(String, String)>,
+}
+
+impl RewriteRules {
+    pub fn from(configs: &[RewriteConfig]) -> Self {
+        let mut exact = HashMap::new();
+        let mut suffixes = Vec::new();
+        configs.iter().for_each(|config| {
+            let from = config.from.trim_end_matches('.').to_lowercase();
+            let to = config.to.trim_end_matches('.').to_lowercase();
+            match from.strip_prefix("*.") {
+                Some(suffix) => suffixes.push((suffix.to_string(), to)),
+                None if to.contains('*') => error!("rewrites里面的规则格式不对, from不是*.后缀的话to不能有*: {} {}",
+                                                   config.from, config.to),
+                None => {
+                    exact.insert(from, to);
+                }
+            }
+        });
+        suffixes.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        RewriteRules {
+            exact,
+            suffixes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.suffixes.is_empty()
+    }
+
+    pub fn rewrite(&self, name: &str) -> Option<String> {
+        let name = name.to_lowercase();
+        if let Some(to) = self.exact.get(&name) {
+            return Some(to.clone());
+        }
+        self.suffixes.iter().find_map(|(suffix, to)| {
+            let prefix = name.strip_suffix(suffix.as_str())?.strip_suffix('.')?;
+            if prefix.is_empty() {
+                return None;
+            }
+            Some(to.replacen('*', prefix, 1))
+        })
+    }
+}
+
+//把查询的域名改写成另一个域名去查, 返回cname和目标域名同类型的记录, 在缓存前面, A查询按原来的域名缓存
+#[derive(Clone)]
+pub struct DomainRewrite {
+    rules: Arc<RewriteRules>,
+    cache_pool: Option<Arc<CachePool>>,
+    resolver: Resolver,
+}
+
+impl DomainRewrite {
+    pub fn new(rules: Arc<RewriteRules>, cache_pool: Option<Arc<CachePool>>, resolver: Resolver) -> Self {
+        DomainRewrite {
+            rules,
+            cache_pool,
+            resolver,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for DomainRewrite {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let target = match self.rules.rewrite(query.get_name()) {
+            Some(target) if &target != query.get_name() => target,
+            _ => return clain.next(query).await,
+        };
+        debug!("{} 改写成 {}", query.get_name(), target);
+        let target_query = query.redirect(&target);
+        //缓存里面只有A记录, 其他类型的查询直接查目标域名
+        let target_answer = match &self.cache_pool {
+            Some(pool) if query.get_type() == 1 => {
+                let name = query.get_name().clone();
+                let resolver = self.resolver.clone();
+                let future = async move {
+                    let mut answer = resolver.resolve(target_query).await?;
+                    //换成原来的域名, 缓存的时候用原来的域名
+                    if let Some(ipv4) = answer.as_mut_any().downcast_mut::<Ipv4Answer>() {
+                        ipv4.set_name(name);
+                    }
+                    Ok(answer)
+                }.boxed();
+                pool.get(query.get_name().clone(), query.get_client_subnet(), future).await?
+            }
+            _ => self.resolver.resolve(target_query).await?,
+        };
+        Ok(cname_answer(&query, &target, Some(&target_answer)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::RewriteConfig;
+    use crate::handler::domain_rewrite::RewriteRules;
+    use crate::handler::tests::{query_buf, test_context};
+    use crate::protocol::{DnsAnswer, Ipv4Answer, LocalAnswer, LocalRecord};
+    use futures_util::FutureExt;
+    use std::net::{Ipv6Addr, SocketAddr};
+    use std::sync::atomic::Ordering;
+
+    fn rules(configs: Vec<(&str, &str)>) -> RewriteRules {
+        let configs: Vec<RewriteConfig> = configs.into_iter()
+            .map(|(from, to)| RewriteConfig { from: from.into(), to: to.into() })
+            .collect();
+        RewriteRules::from(&configs)
+    }
+
+    #[test]
+    fn should_replace_wildcard_when_rewrite_given_suffix_rule() {
+        let rules = rules(vec![("*.internal.example", "*.eu-west.example")]);
+
+        assert_eq!(Some("api.eu-west.example".to_string()), rules.rewrite("api.internal.example"));
+        assert_eq!(Some("a.b.eu-west.example".to_string()), rules.rewrite("A.B.internal.example"));
+        assert_eq!(None, rules.rewrite("internal.example"));
+        assert_eq!(None, rules.rewrite("xinternal.example"));
+    }
+
+    #[test]
+    fn should_prefer_exact_and_longer_suffix_when_rewrite_given_overlapping_rules() {
+        let rules = rules(vec![("*.example.com", "*.example.net"), ("*.api.example.com", "gateway.example.net"),
+                               ("old.example.com.", "new.example.com"), ("bad.example.com", "*.example.net")]);
+
+        assert_eq!(Some("new.example.com".to_string()), rules.rewrite("old.example.com"));
+        assert_eq!(Some("gateway.example.net".to_string()), rules.rewrite("v1.api.example.com"));
+        assert_eq!(Some("www.example.net".to_string()), rules.rewrite("www.example.com"));
+        assert_eq!(Some("bad.example.net".to_string()), rules.rewrite("bad.example.com"));
+    }
+
+    #[tokio::test]
+    async fn should_cache_a_answer_and_return_target_records_when_handle_query_given_rewritten_name() {
+        let rewrites = r#"rewrites = [{ from = "old.example.com", to = "new.example.com" }]"#;
+        let (context, filter, count) = test_context("easydns_rewrite", rewrites).await;
+        let client: SocketAddr = "192.168.1.20:5353".parse().unwrap();
+
+        let first = context.handle_query(query_buf("old.example.com", 1), client).await.unwrap();
+        let second = context.handle_query(query_buf("old.example.com", 1), client).await.unwrap();
+        let aaaa = context.handle_query(query_buf("old.example.com", 28), client).await.unwrap();
+        //缓存里面有原来的域名, 不会再去查
+        let not_found = async { Err("not in cache".into()) }.boxed();
+        let cached = context.cache_pool.as_ref().unwrap()
+            .get("old.example.com".into(), None, not_found).await.unwrap();
+
+        std::fs::remove_file(&filter).unwrap();
+        let records = |answer: &DnsAnswer| answer.as_any().downcast_ref::<LocalAnswer>().unwrap()
+            .get_answers().iter().map(|r| (r.name.clone(), r._type, r.data.clone()))
+            .collect::<Vec<_>>();
+        let target = String::from("new.example.com");
+        let expected = vec![("old.example.com".to_string(), 5, LocalRecord::cname("".into(), 0, &target).data),
+                            (target.clone(), 28, "fd00::1234".parse::<Ipv6Addr>().unwrap().octets().to_vec())];
+        //第二次A查询走了缓存, AAAA查询去上游查了目标域名
+        assert_eq!(2, count.load(Ordering::SeqCst));
+        assert_eq!(2, records(&first).len());
+        assert_eq!(records(&first), records(&second));
+        assert_eq!("old.example.com", cached.as_any().downcast_ref::<Ipv4Answer>().unwrap().get_name());
+        assert_eq!(expected, records(&aaaa));
+    }
+}
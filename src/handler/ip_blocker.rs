@@ -0,0 +1,275 @@
+use async_trait::async_trait;
+use crate::config::Config;
+use crate::handler::{Clain, Handler};
+use crate::handler::cidr::Cidr;
+use crate::handler::server_group::ServerGroup;
+use crate::system::Result;
+use crate::protocol::{DnsAnswer, DnsQuery, FailureAnswer, Ipv4Answer, LocalAnswer, LocalRecord, RawAnswer};
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Arc;
+
+//检查上游返回的ip, 在QuerySender之后, 缓存之前处理
+#[derive(Clone)]
+pub struct IpBlocker {
+    rules: Arc<IpRules>,
+    server_group: Arc<ServerGroup>,
+}
+
+impl IpBlocker {
+    pub fn from(config: &Config, server_group: Arc<ServerGroup>) -> Self {
+        IpBlocker {
+            rules: Arc::new(IpRules::from(config)),
+            server_group,
+        }
+    }
+
+    fn mark_polluted(&self, server: Option<String>, query: &DnsQuery) {
+        match server {
+            Some(server) => self.server_group.mark_polluted(&server),
+            None => warn!("{} 的应答被污染了, 但是不知道是哪个上游服务器返回的", query.get_name()),
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for IpBlocker {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let answer = clain.next(query.clone()).await?;
+        let server = match self.rules.check(answer, &query) {
+            Checked::Answer(answer) => return Ok(answer),
+            Checked::Polluted(server) => server,
+        };
+        self.mark_polluted(server, &query);
+        //被污染的服务器已经不会再用了, 换一个再查一次
+        let answer = self.server_group.send_query(query.clone()).await?;
+        match self.rules.check(answer, &query) {
+            Checked::Answer(answer) => Ok(answer),
+            Checked::Polluted(server) => {
+                self.mark_polluted(server, &query);
+                Ok(FailureAnswer::new(query.get_id(), query.get_name().clone()).into())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum IpAction {
+    //去掉这条记录
+    Drop,
+    //整个应答改成域名不存在
+    NxDomain,
+    //返回这个应答的上游服务器被污染了
+    Polluted,
+}
+
+impl From<&str> for IpAction {
+    fn from(action: &str) -> Self {
+        match action.to_lowercase().as_str() {
+            "drop" => IpAction::Drop,
+            "nxdomain" => IpAction::NxDomain,
+            "polluted" => IpAction::Polluted,
+            _ => {
+                error!("不支持的ip-rules action: {}, 将会返回nxdomain", action);
+                IpAction::NxDomain
+            }
+        }
+    }
+}
+
+enum Checked {
+    Answer(DnsAnswer),
+    //返回应答的上游服务器
+    Polluted(Option<String>),
+}
+
+struct IpRules {
+    rules: Vec<(Cidr, IpAction)>,
+    ttl: u32,
+}
+
+impl IpRules {
+    fn from(config: &Config) -> Self {
+        let rules = config.ip_rules.iter().filter_map(|rule| {
+            match Cidr::parse(&rule.ip) {
+                Some(cidr) => Some((cidr, IpAction::from(rule.action.as_str()))),
+                None => {
+                    error!("ip-rules里面的ip格式不对: {}", rule.ip);
+                    None
+                }
+            }
+        }).collect();
+        IpRules {
+            rules,
+            ttl: config.block_ttl as u32,
+        }
+    }
+
+    fn find(&self, ip: &IpAddr) -> Option<&IpAction> {
+        self.rules.iter().find(|(cidr, _)| cidr.contains(ip)).map(|(_, action)| action)
+    }
+
+    //多个记录命中不同的规则时, polluted优先, 其次是nxdomain
+    fn check(&self, mut answer: DnsAnswer, query: &DnsQuery) -> Checked {
+        let ips: Vec<IpAddr> = if let Some(ipv4) = answer.as_any().downcast_ref::<Ipv4Answer>() {
+            ipv4.get_all_ips().into_iter().map(|ip| IpAddr::V4(*ip)).collect()
+        } else if let Some(raw) = answer.as_any().downcast_ref::<RawAnswer>() {
+            raw.get_answers().iter().filter_map(ipv6).map(IpAddr::V6).collect()
+        } else {
+            return Checked::Answer(answer);
+        };
+        let actions: Vec<&IpAction> = ips.iter().filter_map(|ip| self.find(ip)).collect();
+        if actions.is_empty() {
+            return Checked::Answer(answer);
+        }
+        debug!("{} 的应答命中了ip-rules: {:?}", query.get_name(), actions);
+        if actions.contains(&&IpAction::Polluted) {
+            return Checked::Polluted(answer.get_server().cloned());
+        }
+        let name = query.get_name().clone();
+        if actions.contains(&&IpAction::NxDomain) {
+            let mut local = LocalAnswer::new(query, 3);
+            local.add_authority(LocalRecord::soa(name, self.ttl));
+            return Checked::Answer(local.into());
+        }
+        if let Some(ipv4) = answer.as_mut_any().downcast_mut::<Ipv4Answer>() {
+            ipv4.retain_ips(|ip| self.find(&IpAddr::V4(*ip)).is_none());
+            //ip都被去掉了就返回空的应答
+            if ipv4.is_empty() {
+                let mut local = LocalAnswer::new(query, 0);
+                local.add_authority(LocalRecord::soa(name, self.ttl));
+                return Checked::Answer(local.into());
+            }
+            return Checked::Answer(answer);
+        }
+        //原始报文改不了, 用剩下的记录重新合成一个应答
+        let raw = answer.as_any().downcast_ref::<RawAnswer>().unwrap();
+        let mut local = LocalAnswer::new(query, 0);
+        raw.get_answers().iter()
+            .filter(|record| ipv6(record).map_or(true, |ip| self.find(&IpAddr::V6(ip)).is_none()))
+            .for_each(|record| local.add_answer(record.clone()));
+        if !local.get_answers().iter().any(|record| ipv6(record).is_some()) {
+            local.add_authority(LocalRecord::soa(name, self.ttl));
+        }
+        Checked::Answer(local.into())
+    }
+}
+
+//AAAA记录的ip, 其他记录返回None
+fn ipv6(record: &LocalRecord) -> Option<Ipv6Addr> {
+    if record._type != 28 {
+        return None;
+    }
+    <[u8; 16]>::try_from(record.data.as_slice()).ok().map(Ipv6Addr::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::ip_blocker::{IpAction, IpRules, Checked};
+    use crate::handler::cidr::Cidr;
+    use crate::protocol::{DnsAnswer, DnsQuery, LocalAnswer, LocalRecord, Ipv4Answer};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_remove_matched_ip_when_check_given_drop_rule() {
+        let rules = rules(vec![("10.0.0.0/8", IpAction::Drop)]);
+        let query = DnsQuery::from("www.baidu.com");
+
+        let answer = checked_answer(rules.check(answer(&query, &["10.0.0.1", "1.2.3.4"]), &query));
+
+        let ipv4 = answer.as_any().downcast_ref::<Ipv4Answer>().unwrap();
+        assert_eq!(vec![&Ipv4Addr::new(1, 2, 3, 4)], ipv4.get_all_ips());
+    }
+
+    #[test]
+    fn should_return_empty_answer_when_check_given_all_ip_dropped() {
+        let rules = rules(vec![("10.0.0.0/8", IpAction::Drop)]);
+        let query = DnsQuery::from("www.baidu.com");
+
+        let answer = checked_answer(rules.check(answer(&query, &["10.0.0.1"]), &query));
+
+        let local = answer.as_any().downcast_ref::<LocalAnswer>().unwrap();
+        assert_eq!((0, 0), (local.get_rcode(), local.get_answers().len()));
+    }
+
+    #[test]
+    fn should_return_nxdomain_when_check_given_bogus_nxdomain_ip() {
+        let rules = rules(vec![("10.0.0.0/8", IpAction::Drop), ("1.2.3.4", IpAction::NxDomain)]);
+        let query = DnsQuery::from("www.baidu.com");
+
+        let answer = checked_answer(rules.check(answer(&query, &["10.0.0.1", "1.2.3.4"]), &query));
+
+        let local = answer.as_any().downcast_ref::<LocalAnswer>().unwrap();
+        assert_eq!(3, local.get_rcode());
+    }
+
+    #[test]
+    fn should_return_server_when_check_given_polluted_ip() {
+        let rules = rules(vec![("1.2.3.4", IpAction::NxDomain), ("5.6.7.8", IpAction::Polluted)]);
+        let query = DnsQuery::from("www.baidu.com");
+        let mut polluted = answer(&query, &["1.2.3.4", "5.6.7.8"]);
+        polluted.set_server("8.8.8.8:53".into());
+
+        let result = rules.check(polluted, &query);
+
+        assert!(matches!(result, Checked::Polluted(Some(server)) if server == "8.8.8.8:53"));
+    }
+
+    #[test]
+    fn should_keep_answer_when_check_given_no_rule_matched() {
+        let rules = rules(vec![("10.0.0.0/8", IpAction::NxDomain)]);
+        let query = DnsQuery::from("www.baidu.com");
+
+        let answer = checked_answer(rules.check(answer(&query, &["1.2.3.4"]), &query));
+
+        assert_eq!("(IP, www.baidu.com, 60, 1.2.3.4)", answer.to_string());
+    }
+
+    #[test]
+    fn should_remove_matched_ipv6_when_check_given_aaaa_answer() {
+        let rules = rules(vec![("2001:db8::/32", IpAction::Drop), ("10.0.0.0/8", IpAction::NxDomain)]);
+        let query = DnsQuery::with_type("www.baidu.com", 28);
+
+        let answer = checked_answer(rules.check(answer(&query, &["2001:db8::1", "fd00::1"]), &query));
+
+        let local = answer.as_any().downcast_ref::<LocalAnswer>().unwrap();
+        let ip: IpAddr = "fd00::1".parse().unwrap();
+        assert_eq!(&vec![LocalRecord::ip("www.baidu.com".into(), 60, &ip)], local.get_answers());
+    }
+
+    #[test]
+    fn should_return_nxdomain_when_check_given_aaaa_answer_with_bogus_ip() {
+        let rules = rules(vec![("2001:db8::1", IpAction::NxDomain)]);
+        let query = DnsQuery::with_type("www.baidu.com", 28);
+
+        let answer = checked_answer(rules.check(answer(&query, &["2001:db8::1"]), &query));
+
+        let local = answer.as_any().downcast_ref::<LocalAnswer>().unwrap();
+        assert_eq!(3, local.get_rcode());
+    }
+
+    fn rules(rules: Vec<(&str, IpAction)>) -> IpRules {
+        IpRules {
+            rules: rules.into_iter().map(|(ip, action)| (Cidr::parse(ip).unwrap(), action)).collect(),
+            ttl: 60,
+        }
+    }
+
+    fn answer(query: &DnsQuery, ips: &[&str]) -> DnsAnswer {
+        let mut local = LocalAnswer::new(query, 0);
+        ips.iter().for_each(|ip| {
+            local.add_answer(LocalRecord::ip(query.get_name().clone(), 60, &ip.parse::<IpAddr>().unwrap()));
+        });
+        let bytes = DnsAnswer::from(local).to_bytes();
+        let mut buf = [0u8; 512];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        DnsAnswer::from(buf)
+    }
+
+    fn checked_answer(checked: Checked) -> DnsAnswer {
+        match checked {
+            Checked::Answer(answer) => answer,
+            Checked::Polluted(_) => panic!("应答不应该被污染"),
+        }
+    }
+}
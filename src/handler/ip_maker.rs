@@ -3,9 +3,13 @@ use tokio_icmp::Pinger;
 use std::sync::Arc;
 use crate::handler::{Clain, Handler};
 use crate::system::Result;
-use futures_util::future::select_all;
+use futures_util::future::{select_all, select_ok};
+use futures_util::FutureExt;
 use crate::protocol::{DnsAnswer, Ipv4Answer, DnsQuery};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
 
 #[derive(Clone)]
 pub struct IpChoiceMaker {
@@ -43,6 +47,61 @@ impl Handler for IpChoiceMaker {
     }
 }
 
+//同时连接每个ip的每个端口, 先完成tcp握手的ip胜出, 不需要root权限
+#[derive(Clone)]
+pub struct IpTcpChoiceMaker {
+    ports: Arc<Vec<u16>>,
+    timeout: Duration,
+}
+
+impl IpTcpChoiceMaker {
+    pub fn new(ports: Vec<u16>, timeout: Duration) -> Self {
+        IpTcpChoiceMaker {
+            ports: Arc::new(ports),
+            timeout,
+        }
+    }
+}
+
+//返回最快连上的ip的序号, 都连不上返回None
+async fn fastest_by_tcp(ips: &[Ipv4Addr], ports: &[u16], duration: Duration) -> Option<usize> {
+    let futures: Vec<_> = ips.iter().enumerate().flat_map(|(index, ip)| {
+        ports.iter().map(move |port| {
+            let addr = SocketAddr::new(IpAddr::V4(*ip), *port);
+            async move {
+                match timeout(duration, TcpStream::connect(addr)).await {
+                    Ok(Ok(_)) => Ok(index),
+                    _ => Err(()),
+                }
+            }.boxed()
+        })
+    }).collect();
+    if futures.is_empty() {
+        return None;
+    }
+    select_ok(futures).await.ok().map(|(index, _)| index)
+}
+
+#[async_trait]
+impl Handler for IpTcpChoiceMaker {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let mut answer = clain.next(query).await?;
+        if let Some(ipv4_answer) = answer.as_mut_any().downcast_mut::<Ipv4Answer>() {
+            let ip_vec: Vec<Ipv4Addr> = ipv4_answer.get_all_ips().into_iter().cloned().collect();
+            if ip_vec.is_empty() {
+                return Ok(answer);
+            }
+            let index = if ip_vec.len() == 1 {
+                0
+            } else {
+                fastest_by_tcp(&ip_vec, &self.ports, self.timeout).await.unwrap_or(0)
+            };
+            ipv4_answer.retain_ip(&ip_vec[index]);
+        }
+        Ok(answer)
+    }
+}
+
 #[derive(Clone)]
 pub struct IpFirstMaker;
 
@@ -57,4 +116,34 @@ impl Handler for IpFirstMaker {
         }
         Ok(answer)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::ip_maker::fastest_by_tcp;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn should_return_listening_ip_when_fastest_by_tcp_given_one_reachable_ip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let ips = [Ipv4Addr::new(127, 0, 0, 2), Ipv4Addr::new(127, 0, 0, 1)];
+
+        let index = fastest_by_tcp(&ips, &[port], Duration::from_secs(1)).await;
+
+        assert_eq!(Some(1), index);
+    }
+
+    #[tokio::test]
+    async fn should_return_none_when_fastest_by_tcp_given_no_reachable_ip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let index = fastest_by_tcp(&[Ipv4Addr::new(127, 0, 0, 1)], &[port], Duration::from_millis(200)).await;
+
+        assert_eq!(None, index);
+    }
+}
@@ -0,0 +1,313 @@
+use async_trait::async_trait;
+use crate::config::{Config, IpSetConfig};
+use crate::handler::{Clain, Handler};
+use crate::system::{Result, get_now};
+use crate::protocol::{DnsAnswer, DnsQuery, Ipv4Answer, LocalAnswer, RawAnswer};
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IpSetTarget {
+    Ipset(String),
+    Nftset {
+        family: String,
+        table: String,
+        set: String,
+    },
+}
+
+impl IpSetTarget {
+    fn from(config: &IpSetConfig) -> Option<Self> {
+        match config._type.to_lowercase().as_str() {
+            "ipset" => Some(IpSetTarget::Ipset(config.name.clone())),
+            "nftset" => {
+                let parts: Vec<&str> = config.name.split('#').collect();
+                match parts.as_slice() {
+                    [family, table, set] => Some(IpSetTarget::Nftset {
+                        family: family.to_string(),
+                        table: table.to_string(),
+                        set: set.to_string(),
+                    }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+//往内核的集合里面加ip, 测试的时候换成内存里面的实现
+#[async_trait]
+pub trait SetBackend: Send + Sync {
+    async fn add(&self, target: &IpSetTarget, ip: &IpAddr, timeout_s: u32) -> Result<()>;
+}
+
+//调用ipset和nft命令
+pub struct CommandBackend;
+
+#[async_trait]
+impl SetBackend for CommandBackend {
+    async fn add(&self, target: &IpSetTarget, ip: &IpAddr, timeout_s: u32) -> Result<()> {
+        let output = match target {
+            IpSetTarget::Ipset(name) => Command::new("ipset")
+                .args(&["add", name, &ip.to_string(), "timeout", &timeout_s.to_string(), "-exist"])
+                .output().await?,
+            IpSetTarget::Nftset { family, table, set } => Command::new("nft")
+                .args(&["add", "element", family, table, set, &format!("{{ {} timeout {}s }}", ip, timeout_s)])
+                .output().await?,
+        };
+        if !output.status.success() {
+            return Err(format!("把{}加到{:?}出错: {}", ip, target,
+                               String::from_utf8_lossy(&output.stderr).trim()).into());
+        }
+        Ok(())
+    }
+}
+
+//应答里面的ip和ttl, 上游的A, AAAA记录和本地的A, AAAA记录
+fn answer_ips(answer: &DnsAnswer) -> Vec<(IpAddr, u32)> {
+    if let Some(ipv4) = answer.as_any().downcast_ref::<Ipv4Answer>() {
+        if ipv4.is_empty() {
+            return vec![];
+        }
+        let ttl = ipv4.get_ttl();
+        return ipv4.get_all_ips().into_iter().map(|ip| (IpAddr::V4(*ip), ttl)).collect();
+    }
+    let records = match answer.as_any().downcast_ref::<LocalAnswer>() {
+        Some(local) => local.get_answers(),
+        None => match answer.as_any().downcast_ref::<RawAnswer>() {
+            Some(raw) => raw.get_answers(),
+            None => return vec![],
+        },
+    };
+    records.iter().filter_map(|r| {
+        let ip = match r._type {
+            1 => IpAddr::V4(Ipv4Addr::from(TryInto::<[u8; 4]>::try_into(r.data.as_slice()).ok()?)),
+            28 => IpAddr::V6(Ipv6Addr::from(TryInto::<[u8; 16]>::try_into(r.data.as_slice()).ok()?)),
+            _ => return None,
+        };
+        Some((ip, r.ttl))
+    }).collect()
+}
+
+pub struct IpSets {
+    //域名对应的集合序号
+    domains: HashMap<String, Vec<usize>>,
+    targets: Vec<IpSetTarget>,
+    backend: Box<dyn SetBackend>,
+    //已经加过的ip和超时的时间, 还没过一半的不用重复执行命令
+    added: DashMap<(usize, IpAddr), u128>,
+}
+
+impl IpSets {
+    pub fn from(config: &Config) -> Self {
+        IpSets::with_backend(&config.ip_sets, Box::new(CommandBackend))
+    }
+
+    fn with_backend(configs: &[IpSetConfig], backend: Box<dyn SetBackend>) -> Self {
+        let mut domains: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut targets = Vec::new();
+        configs.iter().for_each(|config| {
+            let target = match IpSetTarget::from(config) {
+                Some(target) => target,
+                None => {
+                    error!("ip-sets里面的集合格式不对: {} {}", config.name, config._type);
+                    return;
+                }
+            };
+            let index = targets.len();
+            targets.push(target);
+            config.domains.iter().for_each(|domain| {
+                domains.entry(domain.trim_matches('.').to_lowercase()).or_default().push(index);
+            });
+        });
+        IpSets {
+            domains,
+            targets,
+            backend,
+            added: DashMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    //域名和它的上级域名配置的集合
+    fn find(&self, name: &str) -> Vec<usize> {
+        let name = name.to_lowercase();
+        let mut indexes = Vec::new();
+        let mut current = name.as_str();
+        loop {
+            self.domains.get(current).into_iter().flatten().for_each(|i| {
+                if !indexes.contains(i) {
+                    indexes.push(*i);
+                }
+            });
+            match current.splitn(2, '.').nth(1) {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        indexes
+    }
+
+    async fn add(&self, indexes: &[usize], ips: Vec<(IpAddr, u32)>) {
+        let now = get_now();
+        //过期的记录删掉, 不然解析过的ip会一直留在内存里面
+        self.added.retain(|_, expire| *expire > now);
+        for index in indexes {
+            for (ip, ttl) in ips.iter() {
+                let timeout_s = (*ttl).max(1);
+                let expire = now + timeout_s as u128 * 1000;
+                let fresh = self.added.get(&(*index, *ip))
+                    .map(|e| *e.value() > now + timeout_s as u128 * 500)
+                    .unwrap_or(false);
+                if fresh {
+                    continue;
+                }
+                match self.backend.add(&self.targets[*index], ip, timeout_s).await {
+                    Ok(()) => {
+                        self.added.insert((*index, *ip), expire);
+                    }
+                    Err(e) => error!("{:?}", e),
+                }
+            }
+        }
+    }
+}
+
+//解析出来的ip加到域名配置的集合里面, 在缓存前面, 缓存里面的应答也会加, 不影响返回给客户端的应答
+#[derive(Clone)]
+pub struct IpSetFiller {
+    sets: Arc<IpSets>,
+}
+
+impl IpSetFiller {
+    pub fn new(sets: Arc<IpSets>) -> Self {
+        IpSetFiller {
+            sets
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for IpSetFiller {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let indexes = self.sets.find(query.get_name());
+        let answer = clain.next(query).await?;
+        if indexes.is_empty() {
+            return Ok(answer);
+        }
+        let ips = answer_ips(&answer);
+        if !ips.is_empty() {
+            let sets = self.sets.clone();
+            tokio::spawn(async move {
+                sets.add(&indexes, ips).await;
+            });
+        }
+        Ok(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use crate::config::IpSetConfig;
+    use crate::handler::ip_set::{IpSets, IpSetTarget, SetBackend, answer_ips};
+    use crate::protocol::{DnsAnswer, DnsQuery, LocalAnswer, LocalRecord};
+    use crate::system::{Result, set_time_base};
+    use std::net::IpAddr;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct MemoryBackend {
+        added: Arc<Mutex<Vec<(IpSetTarget, IpAddr, u32)>>>,
+    }
+
+    #[async_trait]
+    impl SetBackend for MemoryBackend {
+        async fn add(&self, target: &IpSetTarget, ip: &IpAddr, timeout_s: u32) -> Result<()> {
+            self.added.lock().unwrap().push((target.clone(), *ip, timeout_s));
+            Ok(())
+        }
+    }
+
+    fn sets(backend: MemoryBackend) -> IpSets {
+        let config = |domains: Vec<&str>, name: &str, _type: &str| IpSetConfig {
+            domains: domains.into_iter().map(|d| d.into()).collect(),
+            name: name.into(),
+            _type: _type.into(),
+        };
+        IpSets::with_backend(&[config(vec!["netflix.com"], "vpn", "ipset"),
+                                  config(vec!["api.netflix.com"], "inet#fw4#vpn", "nftset"),
+                                  config(vec!["bad.com"], "inet#fw4", "nftset")], Box::new(backend))
+    }
+
+    #[test]
+    fn should_return_sets_of_parent_domains_when_find_given_subdomain() {
+        let sets = sets(MemoryBackend::default());
+
+        assert_eq!(vec![1, 0], sets.find("v1.API.netflix.com"));
+        assert_eq!(vec![0], sets.find("netflix.com"));
+        assert!(sets.find("notnetflix.com").is_empty());
+        assert!(sets.find("bad.com").is_empty());
+    }
+
+    #[test]
+    fn should_return_ips_with_ttl_when_answer_ips_given_local_answer() {
+        let mut local = LocalAnswer::new(&DnsQuery::from("nas.home"), 0);
+        local.add_answer(LocalRecord::ip("nas.home".into(), 60, &"192.168.1.2".parse().unwrap()));
+        local.add_answer(LocalRecord::ip("nas.home".into(), 30, &"fd00::2".parse().unwrap()));
+        local.add_answer(LocalRecord::cname("nas.home".into(), 60, &"x.home".to_string()));
+
+        assert_eq!(vec![("192.168.1.2".parse().unwrap(), 60), ("fd00::2".parse().unwrap(), 30)],
+                   answer_ips(&DnsAnswer::from(local)));
+    }
+
+    #[test]
+    fn should_return_ipv6_with_ttl_when_answer_ips_given_upstream_aaaa_answer() {
+        let mut local = LocalAnswer::new(&DnsQuery::with_type("www.netflix.com", 28), 0);
+        local.add_answer(LocalRecord::cname("www.netflix.com".into(), 60, &"cdn.netflix.com".to_string()));
+        local.add_answer(LocalRecord::ip("cdn.netflix.com".into(), 30, &"2600::1".parse().unwrap()));
+        let bytes = DnsAnswer::from(local).to_bytes();
+        let mut buf = [0u8; 512];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+
+        assert_eq!(vec![("2600::1".parse().unwrap(), 30)], answer_ips(&DnsAnswer::from(buf)));
+    }
+
+    #[tokio::test]
+    async fn should_skip_fresh_ips_when_add_given_same_ip_again() {
+        let backend = MemoryBackend::default();
+        let sets = sets(backend.clone());
+        let ip: IpAddr = "1.2.3.4".parse().unwrap();
+        set_time_base(0);
+
+        sets.add(&[0], vec![(ip, 100)]).await;
+        set_time_base(40_000);
+        sets.add(&[0], vec![(ip, 100)]).await;
+        set_time_base(60_000);
+        sets.add(&[0], vec![(ip, 100)]).await;
+
+        assert_eq!(vec![(IpSetTarget::Ipset("vpn".into()), ip, 100), (IpSetTarget::Ipset("vpn".into()), ip, 100)],
+                   *backend.added.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_remove_expired_ips_when_add_given_other_ip() {
+        let sets = sets(MemoryBackend::default());
+        set_time_base(0);
+
+        sets.add(&[0], vec![("1.2.3.4".parse().unwrap(), 100)]).await;
+        set_time_base(200_000);
+        sets.add(&[0], vec![("5.6.7.8".parse().unwrap(), 100)]).await;
+
+        assert_eq!(1, sets.added.len());
+        assert!(sets.added.contains_key(&(0, "5.6.7.8".parse().unwrap())));
+    }
+}
@@ -0,0 +1,354 @@
+use async_trait::async_trait;
+use crate::config::{Config, LocalRecordConfig};
+use crate::handler::{Clain, Handler, Resolver, answer_records};
+use crate::handler::dhcp_lease::watch_leases;
+use crate::resource::ResourceClient;
+use crate::system::Result;
+use crate::protocol::{DnsAnswer, DnsQuery, LocalAnswer, LocalRecord, MAX_CNAME_DEPTH};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use tokio::io::AsyncBufReadExt;
+
+#[derive(Debug, Clone, PartialEq)]
+enum LocalValue {
+    Ip(IpAddr),
+    Cname(String),
+    Txt(String),
+}
+
+impl LocalValue {
+    fn from(config: &LocalRecordConfig) -> Option<Self> {
+        let ip = config.value.parse::<IpAddr>().ok();
+        match (config._type.to_uppercase().as_str(), ip) {
+            ("", Some(ip)) => Some(LocalValue::Ip(ip)),
+            ("A", Some(ip @ IpAddr::V4(_))) | ("AAAA", Some(ip @ IpAddr::V6(_))) => Some(LocalValue::Ip(ip)),
+            ("", None) | ("CNAME", None) => {
+                Some(LocalValue::Cname(config.value.trim_end_matches('.').to_lowercase()))
+            }
+            ("TXT", _) => Some(LocalValue::Txt(config.value.clone())),
+            _ => None,
+        }
+    }
+
+    fn get_type(&self) -> u16 {
+        match self {
+            LocalValue::Ip(IpAddr::V4(_)) => 1,
+            LocalValue::Ip(IpAddr::V6(_)) => 28,
+            LocalValue::Cname(_) => 5,
+            LocalValue::Txt(_) => 16,
+        }
+    }
+
+    fn to_record(&self, name: String, ttl: u32) -> LocalRecord {
+        match self {
+            LocalValue::Ip(ip) => LocalRecord::ip(name, ttl, ip),
+            LocalValue::Cname(target) => LocalRecord::cname(name, ttl, target),
+            LocalValue::Txt(text) => LocalRecord::txt(name, ttl, std::slice::from_ref(text)),
+        }
+    }
+}
+
+type RecordMap = HashMap<String, Vec<(LocalValue, u32)>>;
+
+fn add_hosts(records: &mut RecordMap, hosts: Vec<(String, IpAddr)>, ttl: u32) {
+    hosts.into_iter().for_each(|(name, ip)| {
+        records.entry(name).or_default().push((LocalValue::Ip(ip), ttl));
+    });
+}
+
+//查询的时候用的本地记录和ip对应的域名
+pub struct LocalSnapshot {
+    records: RecordMap,
+    names: HashMap<IpAddr, Vec<String>>,
+}
+
+impl LocalSnapshot {
+    fn from(records: RecordMap) -> Self {
+        let mut names: HashMap<IpAddr, Vec<String>> = HashMap::new();
+        records.iter().for_each(|(name, values)| {
+            values.iter().for_each(|(value, _)| {
+                if let LocalValue::Ip(ip) = value {
+                    names.entry(*ip).or_default().push(name.clone());
+                }
+            });
+        });
+        names.values_mut().for_each(|v| {
+            v.sort();
+            v.dedup();
+        });
+        LocalSnapshot {
+            records,
+            names,
+        }
+    }
+
+    //ip对应的域名, 反向查询用
+    pub fn reverse_names(&self, ip: &IpAddr) -> Option<&Vec<String>> {
+        self.names.get(ip)
+    }
+}
+
+//配置的记录是固定的, dhcp租约变化的时候和配置的记录合并之后整体替换
+pub struct LocalRecordHolder {
+    base: RecordMap,
+    snapshot: RwLock<Arc<LocalSnapshot>>,
+    //有dhcp租约文件的话记录会动态变化
+    watched: bool,
+}
+
+impl LocalRecordHolder {
+    fn new(base: RecordMap, watched: bool) -> Self {
+        LocalRecordHolder {
+            snapshot: RwLock::new(Arc::new(LocalSnapshot::from(base.clone()))),
+            base,
+            watched,
+        }
+    }
+
+    pub fn get(&self) -> Arc<LocalSnapshot> {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    //dhcp租约写在配置的记录后面, 同名的话都会返回
+    pub fn set_leases(&self, leases: Vec<(String, IpAddr)>, ttl: u32) {
+        let mut records = self.base.clone();
+        add_hosts(&mut records, leases, ttl);
+        *self.snapshot.write().unwrap() = Arc::new(LocalSnapshot::from(records));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.watched && self.base.is_empty()
+    }
+}
+
+impl Default for LocalRecordHolder {
+    fn default() -> Self {
+        LocalRecordHolder::new(RecordMap::new(), false)
+    }
+}
+
+//本地配置的记录, 直接权威应答, 不会发给上游服务器
+#[derive(Clone)]
+pub struct LocalRecords {
+    holder: Arc<LocalRecordHolder>,
+    ttl: u32,
+    resolver: Resolver,
+}
+
+impl LocalRecords {
+    pub async fn from(config: &Config, client: &ResourceClient, resolver: Resolver) -> Self {
+        let ttl = config.local_ttl as u32;
+        let mut records = RecordMap::new();
+        for path in config.local_hosts.iter() {
+            match read_hosts(client, path).await {
+                Ok(hosts) => add_hosts(&mut records, hosts, ttl),
+                Err(e) => error!("读取hosts文件{}出错: {:?}", path, e),
+            }
+        }
+        //local-records写在后面, 同名的记录都会返回
+        config.local_records.iter().for_each(|record| {
+            match LocalValue::from(record) {
+                Some(value) => {
+                    let record_ttl = if record.ttl == 0 { ttl } else { record.ttl as u32 };
+                    records.entry(record.name.trim_end_matches('.').to_lowercase()).or_default()
+                        .push((value, record_ttl));
+                }
+                None => error!("local-records里面的记录格式不对: {} {} {}",
+                               record.name, record._type, record.value),
+            }
+        });
+        info!("加载了{}个本地域名", records.len());
+        let holder = Arc::new(LocalRecordHolder::new(records, !config.dhcp_lease_file.is_empty()));
+        if !config.dhcp_lease_file.is_empty() {
+            watch_leases(holder.clone(), client.clone(), config).await;
+        }
+        LocalRecords {
+            holder,
+            ttl,
+            resolver,
+        }
+    }
+
+    pub fn holder(&self) -> Arc<LocalRecordHolder> {
+        self.holder.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.holder.is_empty()
+    }
+}
+
+#[async_trait]
+impl Handler for LocalRecords {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let name = query.get_name().to_lowercase();
+        let snapshot = self.holder.get();
+        if !snapshot.records.contains_key(&name) {
+            return clain.next(query).await;
+        }
+        let (mut answer, external) = local_answer(&snapshot.records, &query, name, self.ttl);
+        //cname指向的域名不在本地, 去上游查一下, 带上目标域名同类型的记录
+        if let Some(target) = external {
+            let target_answer = self.resolver.resolve(query.redirect(&target)).await?;
+            answer_records(&target_answer, &target).into_iter().for_each(|r| answer.add_answer(r));
+        }
+        Ok(answer.into())
+    }
+}
+
+//返回本地的应答和不在本地的cname目标
+fn local_answer(records: &RecordMap, query: &DnsQuery, name: String, ttl: u32)
+                -> (LocalAnswer, Option<String>) {
+    let mut answer = LocalAnswer::new(query, 0);
+    answer.set_authoritative();
+    let _type = query.get_type();
+    let mut current = name;
+    for _ in 0..MAX_CNAME_DEPTH {
+        let values = match records.get(&current) {
+            Some(values) => values,
+            None => return (answer, Some(current)),
+        };
+        let matched: Vec<&(LocalValue, u32)> = values.iter().filter(|(v, _)| v.get_type() == _type).collect();
+        if !matched.is_empty() {
+            matched.into_iter().for_each(|(v, ttl)| answer.add_answer(v.to_record(current.clone(), *ttl)));
+            return (answer, None);
+        }
+        match values.iter().find(|(v, _)| v.get_type() == 5) {
+            Some((cname @ LocalValue::Cname(target), ttl)) => {
+                answer.add_answer(cname.to_record(current.clone(), *ttl));
+                current = target.clone();
+            }
+            //域名存在但是没有这个类型的记录
+            _ => {
+                answer.add_authority(LocalRecord::soa(current, ttl));
+                return (answer, None);
+            }
+        }
+    }
+    warn!("{} 的本地cname太深了", query.get_name());
+    (answer, None)
+}
+
+//hosts文件, 一行是 ip 域名1 域名2 ..., #后面是注释
+async fn read_hosts(client: &ResourceClient, path: &str) -> Result<Vec<(String, IpAddr)>> {
+    let mut reader = client.open(path).await?;
+    let mut hosts = Vec::new();
+    let mut buffer = String::new();
+    while reader.read_line(&mut buffer).await? > 0 {
+        hosts.extend(parse_hosts_line(&buffer));
+        buffer.clear();
+    }
+    Ok(hosts)
+}
+
+fn parse_hosts_line(line: &str) -> Vec<(String, IpAddr)> {
+    let line = line.split('#').next().unwrap_or("");
+    let mut split = line.split_whitespace();
+    match split.next().and_then(|ip| ip.parse::<IpAddr>().ok()) {
+        Some(ip) => split.map(|name| (name.trim_end_matches('.').to_lowercase(), ip)).collect(),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::LocalRecordConfig;
+    use crate::handler::local_records::{LocalValue, RecordMap, LocalRecordHolder, local_answer, parse_hosts_line};
+    use crate::handler::tests::{query_buf, test_context};
+    use crate::protocol::{DnsQuery, LocalAnswer, LocalRecord};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    fn records(configs: Vec<(&str, &str, &str)>) -> RecordMap {
+        let mut map = RecordMap::new();
+        configs.into_iter().for_each(|(name, _type, value)| {
+            let config = LocalRecordConfig { name: name.into(), _type: _type.into(), value: value.into(), ttl: 0 };
+            map.entry(name.into()).or_default().push((LocalValue::from(&config).unwrap(), 60));
+        });
+        map
+    }
+
+    #[test]
+    fn should_infer_type_when_from_given_record_config() {
+        let config = |t: &str, v: &str| LocalRecordConfig { name: "a.lan".into(), _type: t.into(), value: v.into(), ttl: 0 };
+
+        assert_eq!(Some(LocalValue::Ip("192.168.1.2".parse().unwrap())), LocalValue::from(&config("", "192.168.1.2")));
+        assert_eq!(Some(LocalValue::Cname("nas.home".into())), LocalValue::from(&config("", "NAS.home.")));
+        assert_eq!(Some(LocalValue::Txt("hello".into())), LocalValue::from(&config("txt", "hello")));
+        assert_eq!(None, LocalValue::from(&config("AAAA", "192.168.1.2")));
+        assert_eq!(None, LocalValue::from(&config("MX", "mail.lan")));
+    }
+
+    #[test]
+    fn should_return_a_records_when_answer_given_local_name() {
+        let records = records(vec![("nas.home", "A", "192.168.1.2"), ("nas.home", "TXT", "nas")]);
+        let query = DnsQuery::from("nas.home");
+
+        let (answer, external) = local_answer(&records, &query, "nas.home".into(), 60);
+
+        assert_eq!(None, external);
+        assert_eq!(vec![LocalRecord::ip("nas.home".into(), 60, &IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)))],
+                   *answer.get_answers());
+    }
+
+    #[test]
+    fn should_follow_local_cname_when_answer_given_cname_chain() {
+        let records = records(vec![("www.home", "CNAME", "nas.home"), ("nas.home", "A", "192.168.1.2"),
+                                   ("api.example.com", "CNAME", "staging.example.com")]);
+
+        let (answer, external) = local_answer(&records, &DnsQuery::from("www.home"), "www.home".into(), 60);
+        let (_, staging) = local_answer(&records, &DnsQuery::from("api.example.com"), "api.example.com".into(), 60);
+
+        assert_eq!(None, external);
+        assert_eq!(2, answer.get_answers().len());
+        assert_eq!(Some("staging.example.com".to_string()), staging);
+    }
+
+    #[test]
+    fn should_return_no_data_when_answer_given_other_type() {
+        let records = records(vec![("nas.home", "A", "192.168.1.2")]);
+
+        let (answer, external) = local_answer(&records, &DnsQuery::with_type("nas.home", 28), "nas.home".into(), 60);
+
+        assert_eq!(None, external);
+        assert_eq!((0, 0), (answer.get_rcode(), answer.get_answers().len()));
+    }
+
+    #[test]
+    fn should_merge_leases_with_base_records_when_set_leases_given_new_leases() {
+        let holder = LocalRecordHolder::new(records(vec![("nas.home", "A", "192.168.1.2")]), true);
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+
+        holder.set_leases(vec![("laptop.lan".into(), ip)], 60);
+        holder.set_leases(vec![("phone.lan".into(), ip)], 60);
+
+        let snapshot = holder.get();
+        assert!(snapshot.records.contains_key("nas.home"));
+        assert!(!snapshot.records.contains_key("laptop.lan"));
+        assert_eq!(Some(&vec!["phone.lan".to_string()]), snapshot.reverse_names(&ip));
+    }
+
+    #[test]
+    fn should_return_names_when_parse_given_hosts_line() {
+        let ip: IpAddr = "192.168.1.3".parse().unwrap();
+
+        assert_eq!(vec![("printer.lan".to_string(), ip), ("printer".to_string(), ip)],
+                   parse_hosts_line("192.168.1.3 Printer.lan printer # office\n"));
+        assert!(parse_hosts_line("# 192.168.1.3 printer.lan").is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_return_cname_and_upstream_records_when_handle_query_given_external_cname_and_aaaa_query() {
+        let local = r#"local-records = [{ name = "www.home", type = "CNAME", value = "www.example.com", ttl = 60 }]"#;
+        let (context, filter, _) = test_context("easydns_local_external_cname", local).await;
+        let client: SocketAddr = "192.168.1.20:5353".parse().unwrap();
+
+        let answer = context.handle_query(query_buf("www.home", 28), client).await.unwrap();
+
+        std::fs::remove_file(&filter).unwrap();
+        let target = String::from("www.example.com");
+        let expected = vec![LocalRecord::cname("www.home".into(), 60, &target),
+                            LocalRecord::ip(target.clone(), 60, &"fd00::1234".parse().unwrap())];
+        assert_eq!(expected, *answer.as_any().downcast_ref::<LocalAnswer>().unwrap().get_answers());
+    }
+}
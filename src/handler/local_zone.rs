@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use crate::config::Config;
+use crate::handler::{Clain, Handler};
+use crate::resource::ResourceClient;
+use crate::system::Result;
+use crate::protocol::{DnsAnswer, DnsQuery};
+use crate::zone::Zone;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+//区域里面的域名都由本地回答, 不会发给上游服务器
+#[derive(Clone)]
+pub struct LocalZones {
+    zones: Arc<Vec<Zone>>,
+}
+
+impl LocalZones {
+    pub async fn from(config: &Config, client: &ResourceClient) -> Self {
+        let mut zones = Vec::new();
+        for source in config.zones.iter() {
+            match read_zone(client, &source.path, &source.origin).await {
+                Ok(zone) => {
+                    info!("加载了区域 {}", zone.get_origin());
+                    zones.push(zone);
+                }
+                Err(e) => error!("读取zone文件{}出错: {:?}", source.path, e),
+            }
+        }
+        //子区域在前面, 先匹配最具体的区域
+        zones.sort_by_key(|z| std::cmp::Reverse(z.get_origin().len()));
+        LocalZones {
+            zones: Arc::new(zones),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+}
+
+async fn read_zone(client: &ResourceClient, path: &str, origin: &str) -> Result<Zone> {
+    let mut text = String::new();
+    client.open(path).await?.read_to_string(&mut text).await?;
+    Zone::parse(&text, origin)
+}
+
+#[async_trait]
+impl Handler for LocalZones {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let name = query.get_name().to_lowercase();
+        match self.zones.iter().find(|z| z.contains(&name)) {
+            Some(zone) => Ok(zone.answer(&query).into()),
+            None => clain.next(query).await,
+        }
+    }
+}
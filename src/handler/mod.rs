@@ -3,76 +3,293 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use tokio_icmp::Pinger;
 
-use crate::cache::CachePool;
+use crate::cache::{CachePool, read_warm_up_domains};
 use crate::config::Config;
-use crate::filter::Filter;
+use crate::filter::{FilterStats, StatsReport};
 use crate::handler::cache_handler::CacheHandler;
+use crate::handler::client_group::{ClientPolicies, Policy};
 use crate::handler::domain_filter::DomainFilter;
-use crate::handler::ip_maker::{IpChoiceMaker, IpFirstMaker};
+use crate::handler::cname_filter::CnameFilter;
+use crate::handler::ip_maker::{IpChoiceMaker, IpFirstMaker, IpTcpChoiceMaker};
+use crate::handler::ip_blocker::IpBlocker;
+use crate::handler::ip_set::{IpSetFiller, IpSets};
 use crate::handler::legal_checker::LegalChecker;
 use crate::handler::query_sender::QuerySender;
+use crate::handler::safe_search::SafeSearch;
+use crate::handler::domain_rewrite::{DomainRewrite, RewriteRules};
+use crate::handler::local_records::LocalRecords;
+use crate::handler::local_zone::LocalZones;
+use crate::handler::reverse_lookup::ReverseLookup;
 use crate::system::{Result, QueryBuf};
 use crate::handler::server_group::ServerGroup;
 use std::option::Option::Some;
-use crate::protocol::{DnsAnswer, DnsQuery};
+use crate::protocol::{DnsAnswer, DnsQuery, Ipv4Answer, LocalAnswer, LocalRecord, RawAnswer};
+use crate::resource::ResourceClient;
+use futures_util::FutureExt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
 
 mod legal_checker;
 mod cache_handler;
 mod query_sender;
 mod ip_maker;
+mod ip_blocker;
+mod ip_set;
 mod domain_filter;
+mod client_group;
+mod cidr;
+mod cname_filter;
+mod safe_search;
+mod domain_rewrite;
+mod local_records;
+mod dhcp_lease;
+mod local_zone;
+mod reverse_lookup;
 mod server_group;
 
 pub struct HandlerContext {
     server_group: Arc<ServerGroup>,
-    pinger: Option<Arc<Pinger>>,
     cache_pool: Option<Arc<CachePool>>,
-    filter: Arc<Filter>,
+    policies: Arc<ClientPolicies>,
+    stats: Arc<FilterStats>,
+    local_records: Option<LocalRecords>,
+    local_zones: Option<LocalZones>,
+    reverse_lookup: Option<ReverseLookup>,
+    rewrite_rules: Option<Arc<RewriteRules>>,
+    ip_sets: Option<Arc<IpSets>>,
+    cname_filter: Option<CnameFilter>,
+    upstream: Upstream,
 }
 
 impl HandlerContext {
     pub async fn from(config: Config) -> Result<Self> {
-        let pinger = if config.ip_choose_strategy == 0 {
-            None
-        } else {
+        if config.ip_choose_strategy > 2 {
+            error!("不支持的ip-choose-strategy: {}, 将会选择第一个ip", config.ip_choose_strategy);
+        }
+        let pinger = if config.ip_choose_strategy == 1 {
             Some(Arc::new(Pinger::new().await?))
+        } else {
+            None
         };
         let server_group = Arc::new(ServerGroup::from(
             config.servers.clone(),
             config.server_choose_strategy.clone(),
             (config.server_choose_duration_h * 60 * 60) as u64,
+            (config.ip_rule_polluted_duration_m * 60 * 1000) as u128,
         ).await?);
         let cache_pool = if config.cache_on {
             Some(Arc::new(CachePool::from(&config).await?))
         } else {
             None
         };
-        let filter = Arc::new(Filter::from(&config).await);
+        let resource_client = ResourceClient::from(&config).await?;
+        let policies = Arc::new(ClientPolicies::from(&config, resource_client.clone()).await);
+        let stats = FilterStats::from(&config);
+        let upstream = Upstream {
+            pinger,
+            tcp_maker: if config.ip_choose_strategy == 2 {
+                Some(IpTcpChoiceMaker::new(config.ip_choose_tcp_ports.clone(),
+                                           Duration::from_millis(config.ip_choose_tcp_timeout_ms as u64)))
+            } else {
+                None
+            },
+            server_group: server_group.clone(),
+            ip_blocker: if config.ip_rules.is_empty() {
+                None
+            } else {
+                Some(IpBlocker::from(&config, server_group.clone()))
+            },
+        };
+        let cname_filter = if config.filter_cname {
+            Some(CnameFilter::new(policies.clone(), stats.clone()))
+        } else {
+            None
+        };
+        let resolver = Resolver {
+            cache_pool: cache_pool.clone(),
+            cname_filter: cname_filter.clone(),
+            upstream: upstream.clone(),
+        };
+        let local_records = LocalRecords::from(&config, &resource_client, resolver).await;
+        let local_zones = LocalZones::from(&config, &resource_client).await;
+        let rewrite_rules = RewriteRules::from(&config.rewrites);
+        let ip_sets = IpSets::from(&config);
+        let reverse_lookup = ReverseLookup::from(&config, local_records.holder()).await?;
+        if let Some(pool) = cache_pool.clone() {
+            if !config.cache_warmup.is_empty() {
+                start_warm_up_task(pool, policies.default().clone(), upstream.clone(), resource_client,
+                                   config.cache_warmup.clone(), config.cache_warmup_concurrency);
+            }
+        }
         Ok(HandlerContext {
             server_group,
-            pinger,
             cache_pool,
-            filter,
+            policies,
+            stats,
+            local_records: if local_records.is_empty() { None } else { Some(local_records) },
+            local_zones: if local_zones.is_empty() { None } else { Some(local_zones) },
+            reverse_lookup: if reverse_lookup.is_empty() { None } else { Some(reverse_lookup) },
+            rewrite_rules: if rewrite_rules.is_empty() { None } else { Some(Arc::new(rewrite_rules)) },
+            ip_sets: if ip_sets.is_empty() { None } else { Some(Arc::new(ip_sets)) },
+            cname_filter,
+            upstream,
         })
     }
 
-    pub async fn handle_query(&self, buf: QueryBuf) -> Result<DnsAnswer> {
+    pub fn filter_report(&self) -> StatsReport {
+        self.stats.report()
+    }
+
+    pub async fn persist_cache(&self) {
+        if let Some(pool) = &self.cache_pool {
+            if let Err(e) = pool.write_to_file().await {
+                error!("把缓存写入文件出错: {:?}", e)
+            }
+        }
+    }
+
+    fn resolver(&self) -> Resolver {
+        Resolver {
+            cache_pool: self.cache_pool.clone(),
+            cname_filter: self.cname_filter.clone(),
+            upstream: self.upstream.clone(),
+        }
+    }
+
+    pub async fn handle_query(&self, buf: QueryBuf, src: SocketAddr) -> Result<DnsAnswer> {
         let mut query_clain = Clain::new();
-        query_clain.add(DomainFilter::new(self.filter.clone()));
+        if let Some(local_records) = self.local_records.clone() {
+            query_clain.add(local_records);
+        }
+        if let Some(local_zones) = self.local_zones.clone() {
+            query_clain.add(local_zones);
+        }
+        //反向区域写在zone文件里面的话由上面的区域回答
+        if let Some(reverse_lookup) = self.reverse_lookup.clone() {
+            query_clain.add(reverse_lookup);
+        }
+        query_clain.add(DomainFilter::new(self.policies.clone(), self.stats.clone()));
+        //被拦截的不加, 后面的安全搜索, 改写, 缓存和上游的应答都会加
+        if let Some(ip_sets) = self.ip_sets.clone() {
+            query_clain.add(IpSetFiller::new(ip_sets));
+        }
+        let resolver = self.resolver();
+        query_clain.add(SafeSearch::new(self.policies.clone(), resolver.clone()));
+        //改写的应答按原来的域名缓存
+        if let Some(rules) = self.rewrite_rules.clone() {
+            query_clain.add(DomainRewrite::new(rules, self.cache_pool.clone(), resolver));
+        }
         query_clain.add(LegalChecker::new(self.server_group.clone()));
+        //缓存是所有客户端组共用的, 缓存里面的应答也要按客户端的规则检查cname
+        if let Some(cname_filter) = self.cname_filter.clone() {
+            query_clain.add(cname_filter);
+        }
         if let Some(pool) = self.cache_pool.clone() {
             query_clain.add(CacheHandler::new(pool));
         }
+        self.upstream.add_to(&mut query_clain);
+        let mut query = DnsQuery::from(buf);
+        query.set_client(src.ip());
+        query_clain.next(query).await
+    }
+}
+
+//缓存之后的处理器, 缓存预热时也要经过这些处理器
+#[derive(Clone)]
+struct Upstream {
+    pinger: Option<Arc<Pinger>>,
+    tcp_maker: Option<IpTcpChoiceMaker>,
+    server_group: Arc<ServerGroup>,
+    ip_blocker: Option<IpBlocker>,
+}
+
+impl Upstream {
+    fn add_to(&self, clain: &mut Clain) {
         if let Some(pinger) = self.pinger.clone() {
-            query_clain.add(IpChoiceMaker::new(pinger));
+            clain.add(IpChoiceMaker::new(pinger));
+        } else if let Some(tcp_maker) = self.tcp_maker.clone() {
+            clain.add(tcp_maker);
         } else {
-            query_clain.add(IpFirstMaker);
+            clain.add(IpFirstMaker);
+        }
+        //最先拿到上游服务器的应答
+        if let Some(ip_blocker) = self.ip_blocker.clone() {
+            clain.add(ip_blocker);
         }
-        query_clain.add(QuerySender::new(self.server_group.clone()));
-        query_clain.next(DnsQuery::from(buf)).await
+        clain.add(QuerySender::new(self.server_group.clone()));
     }
 }
 
+//本地合成应答的时候用来查询别的域名, 和普通的查询一样经过缓存和上游的处理器
+#[derive(Clone)]
+struct Resolver {
+    cache_pool: Option<Arc<CachePool>>,
+    cname_filter: Option<CnameFilter>,
+    upstream: Upstream,
+}
+
+impl Resolver {
+    async fn resolve(&self, query: DnsQuery) -> Result<DnsAnswer> {
+        let mut clain = Clain::new();
+        if let Some(cname_filter) = self.cname_filter.clone() {
+            clain.add(cname_filter);
+        }
+        if let Some(pool) = self.cache_pool.clone() {
+            clain.add(CacheHandler::new(pool));
+        }
+        self.upstream.add_to(&mut clain);
+        clain.next(query).await
+    }
+}
+
+//目标域名查不到的时候cname记录的ttl
+const CNAME_TTL: u32 = 300;
+
+//把查询的域名指向target, 带上target同类型的记录, 安全搜索和改写规则用
+fn cname_answer(query: &DnsQuery, target: &String,
+                target_answer: Option<&DnsAnswer>) -> LocalAnswer {
+    let records = target_answer.map(|a| answer_records(a, target)).unwrap_or(vec![]);
+    let ttl = records.iter().map(|r| r.ttl).min().unwrap_or(CNAME_TTL);
+    let mut answer = LocalAnswer::new(query, 0);
+    answer.add_answer(LocalRecord::cname(query.get_name().clone(), ttl, target));
+    records.into_iter().for_each(|r| answer.add_answer(r));
+    answer
+}
+
+//应答里面的记录, 上游的A记录合并成了一个应答, 按target的名字展开
+fn answer_records(answer: &DnsAnswer, target: &String) -> Vec<LocalRecord> {
+    if let Some(ipv4) = answer.as_any().downcast_ref::<Ipv4Answer>().filter(|a| !a.is_empty()) {
+        let ttl = ipv4.get_ttl();
+        return ipv4.get_all_ips().into_iter()
+            .map(|ip| LocalRecord::ip(target.clone(), ttl, &IpAddr::V4(*ip)))
+            .collect();
+    }
+    if let Some(local) = answer.as_any().downcast_ref::<LocalAnswer>() {
+        return local.get_answers().clone();
+    }
+    match answer.as_any().downcast_ref::<RawAnswer>() {
+        Some(raw) => raw.get_answers().clone(),
+        None => vec![],
+    }
+}
+
+//预热的域名没有客户端, 用全局的规则过滤
+fn start_warm_up_task(pool: Arc<CachePool>, policy: Policy, upstream: Upstream,
+                      client: ResourceClient, paths: Vec<String>, concurrency: usize) {
+    tokio::spawn(async move {
+        let current = policy.filter.get();
+        let domains: Vec<String> = read_warm_up_domains(&client, &paths).await.into_iter()
+            .filter(|d| !current.contain(d))
+            .collect();
+        info!("开始缓存预热, 共{}个域名", domains.len());
+        pool.warm_up(domains, concurrency, |domain| {
+            let mut clain = Clain::new();
+            upstream.add_to(&mut clain);
+            clain.next(DnsQuery::from(domain.as_str())).boxed()
+        }).await;
+    });
+}
+
 struct Clain {
     pub funcs: Vec<Box<dyn Handler>>,
 }
@@ -95,3 +312,105 @@ impl Clain {
 trait Handler: Send + Sync {
     async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer>;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::handler::HandlerContext;
+    use crate::protocol::{Answer, DnsQuery, Ipv4Answer, LocalAnswer, LocalRecord};
+    use crate::system::QueryBuf;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::UdpSocket;
+    use toml::Value;
+
+    //本地模拟的上游服务器, www.a.com 指向 c.tracker.com, 其他域名直接返回A或者AAAA记录, 返回收到的查询次数
+    async fn upstream_stub() -> (String, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        let count = Arc::new(AtomicUsize::new(0));
+        let cloned_count = count.clone();
+        tokio::spawn(async move {
+            loop {
+                let mut buf: QueryBuf = [0u8; 256];
+                let (_, from) = socket.recv_from(&mut buf).await.unwrap();
+                cloned_count.fetch_add(1, Ordering::SeqCst);
+                let query = DnsQuery::from(buf);
+                let mut answer = LocalAnswer::new(&query, 0);
+                let mut name = query.get_name().clone();
+                if name == "www.a.com" {
+                    let target = String::from("c.tracker.com");
+                    answer.add_answer(LocalRecord::cname(name, 60, &target));
+                    name = target;
+                }
+                let ip: Option<IpAddr> = match query.get_type() {
+                    1 => Some(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))),
+                    28 => Some("fd00::1234".parse().unwrap()),
+                    _ => None,
+                };
+                if let Some(ip) = ip {
+                    answer.add_answer(LocalRecord::ip(name, 60, &ip));
+                }
+                socket.send_to(&answer.to_bytes(), from).await.unwrap();
+            }
+        });
+        (address, count)
+    }
+
+    pub fn query_buf(domain: &str, _type: u16) -> QueryBuf {
+        let bytes: Vec<u8> = (&DnsQuery::with_type(domain, _type)).into();
+        let mut buf = [0u8; 256];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        buf
+    }
+
+    //默认规则拦截tracker.com, 192.168.1.10所在的分组不拦截, extra是额外的配置, 返回的filter文件用完要删掉
+    pub async fn test_context(name: &str, extra: &str)
+                              -> (HandlerContext, PathBuf, Arc<AtomicUsize>) {
+        let dir = std::env::temp_dir();
+        let filter = dir.join(format!("{}.txt", name));
+        std::fs::write(&filter, "address /tracker.com/#\n").unwrap();
+        let (server, count) = upstream_stub().await;
+        let toml = format!(r#"
+            cache-file = "{}"
+            cache = true
+            cache-num = 100
+            cache-snapshot-duration-m = 0
+            port = 0
+            servers = ["{}"]
+            log-level = "error"
+            ip-choose-strategy = 0
+            cache-get-strategy = 0
+            cache-ttl-timeout-ms = 0
+            server-choose-strategy = 1
+            server-choose-duration-h = 0
+            filters = ["{}"]
+            filter-cname = true
+            client-groups = [{{ name = "open", clients = ["192.168.1.10"], filters = [] }}]
+            {}
+        "#, dir.join(name).display(), server, filter.display(), extra);
+        let context = HandlerContext::from(Config::from(toml.parse::<Value>().unwrap())).await.unwrap();
+        (context, filter, count)
+    }
+
+    #[tokio::test]
+    async fn should_block_cached_answer_and_count_once_when_handle_query_given_cname_blocked_for_client() {
+        let (context, filter, count) = test_context("easydns_cname_filter", "").await;
+        let open: SocketAddr = "192.168.1.10:5353".parse().unwrap();
+        let other: SocketAddr = "192.168.1.20:5353".parse().unwrap();
+
+        //不拦截的分组先查询, 应答进了缓存
+        let allowed = context.handle_query(query_buf("www.a.com", 1), open).await.unwrap();
+        let blocked = context.handle_query(query_buf("www.a.com", 1), other).await.unwrap();
+
+        std::fs::remove_file(&filter).unwrap();
+        let window = &context.filter_report().windows[0];
+        assert_eq!(1, count.load(Ordering::SeqCst));
+        assert_eq!(["c.tracker.com".to_string()], allowed.get_cnames());
+        assert!(allowed.as_any().downcast_ref::<Ipv4Answer>().is_some());
+        assert!(blocked.as_any().downcast_ref::<LocalAnswer>().unwrap().get_answers().is_empty());
+        assert_eq!((1, 1), (window.blocked, window.allowed));
+    }
+}
@@ -0,0 +1,218 @@
+use async_trait::async_trait;
+use crate::config::Config;
+use crate::handler::{Clain, Handler};
+use crate::handler::local_records::LocalRecordHolder;
+use crate::handler::server_group::ServerGroup;
+use crate::system::Result;
+use crate::protocol::{DnsAnswer, DnsQuery, LocalAnswer, LocalRecord};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+//私有地址的反向区域, RFC 1918和ULA
+fn private_zones() -> Vec<String> {
+    let mut zones = vec![String::from("10.in-addr.arpa"), String::from("168.192.in-addr.arpa")];
+    zones.extend((16..32).map(|i| format!("{}.172.in-addr.arpa", i)));
+    zones.push("c.f.ip6.arpa".into());
+    zones.push("d.f.ip6.arpa".into());
+    zones
+}
+
+fn in_zone(name: &str, zone: &str) -> bool {
+    name == zone || (name.ends_with(zone) && name[..name.len() - zone.len()].ends_with('.'))
+}
+
+//4.3.2.1.in-addr.arpa 和 32个半字节的ip6.arpa 转成ip, 不是完整的地址返回None
+fn parse_reverse(name: &str) -> Option<IpAddr> {
+    if let Some(labels) = name.strip_suffix(".in-addr.arpa") {
+        let mut octets = labels.split('.').map(|l| l.parse::<u8>().ok()).collect::<Option<Vec<u8>>>()?;
+        if octets.len() != 4 {
+            return None;
+        }
+        octets.reverse();
+        return Some(IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])));
+    }
+    let labels = name.strip_suffix(".ip6.arpa")?;
+    let nibbles = labels.split('.').rev().map(|l| {
+        if l.len() == 1 { u8::from_str_radix(l, 16).ok() } else { None }
+    }).collect::<Option<Vec<u8>>>()?;
+    if nibbles.len() != 32 {
+        return None;
+    }
+    let value = nibbles.iter().fold(0u128, |v, n| (v << 4) | *n as u128);
+    Some(IpAddr::V6(Ipv6Addr::from(value)))
+}
+
+//反向查询, 本地知道的ip直接返回域名, 私有地址不知道的返回NXDOMAIN, 指定的反向区域转发给内网的服务器
+#[derive(Clone)]
+pub struct ReverseLookup {
+    names: Arc<LocalRecordHolder>,
+    zones: Arc<Vec<String>>,
+    forwards: Arc<Vec<(String, Arc<ServerGroup>)>>,
+    ttl: u32,
+}
+
+impl ReverseLookup {
+    pub async fn from(config: &Config, names: Arc<LocalRecordHolder>) -> Result<Self> {
+        let mut forwards = Vec::new();
+        for forward in config.ptr_forwards.iter() {
+            let server_group = ServerGroup::from(vec![forward.server.clone()], 1, 0, 0).await?;
+            forwards.push((forward.zone.trim_end_matches('.').to_lowercase(), Arc::new(server_group)));
+        }
+        //转发的区域有重叠时, 按最长的区域转发
+        forwards.sort_by_key(|(zone, _)| std::cmp::Reverse(zone.len()));
+        Ok(ReverseLookup {
+            names,
+            zones: Arc::new(if config.private_ptr { private_zones() } else { vec![] }),
+            forwards: Arc::new(forwards),
+            ttl: config.local_ttl as u32,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.zones.is_empty() && self.forwards.is_empty()
+    }
+
+    //不需要本地回答的返回None
+    fn answer(&self, query: &DnsQuery, name: &str) -> Option<LocalAnswer> {
+        let snapshot = self.names.get();
+        let known = parse_reverse(name).and_then(|ip| snapshot.reverse_names(&ip));
+        let zone = self.zones.iter().find(|z| in_zone(name, z));
+        if known.is_none() && zone.is_none() {
+            return None;
+        }
+        let mut answer = LocalAnswer::new(query, if known.is_some() { 0 } else { 3 });
+        answer.set_authoritative();
+        match known {
+            Some(targets) if query.get_type() == 12 => targets.iter().for_each(|target| {
+                answer.add_answer(LocalRecord::with_name(name.into(), 12, self.ttl, target));
+            }),
+            _ => answer.add_authority(LocalRecord::soa(zone.map(|z| z.as_str()).unwrap_or(name).into(), self.ttl)),
+        }
+        Some(answer)
+    }
+}
+
+#[async_trait]
+impl Handler for ReverseLookup {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        let name = query.get_name().to_lowercase();
+        if !name.ends_with(".arpa") {
+            return clain.next(query).await;
+        }
+        if let Some((_, server_group)) = self.forwards.iter().find(|(zone, _)| in_zone(&name, zone)) {
+            return server_group.send_query(query).await;
+        }
+        match self.answer(&query, &name) {
+            Some(answer) => Ok(answer.into()),
+            None => clain.next(query).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::{Clain, Handler};
+    use crate::handler::local_records::LocalRecordHolder;
+    use crate::handler::reverse_lookup::{parse_reverse, private_zones, in_zone, ReverseLookup};
+    use crate::handler::server_group::ServerGroup;
+    use crate::protocol::DnsQuery;
+    use std::sync::Arc;
+    use tokio::net::UdpSocket;
+
+    fn ptr_query(name: &str) -> DnsQuery {
+        DnsQuery::with_type(name, 12)
+    }
+
+    fn lookup(names: Vec<(&str, &str)>) -> ReverseLookup {
+        let holder = LocalRecordHolder::default();
+        holder.set_leases(names.into_iter().map(|(ip, name)| (name.into(), ip.parse().unwrap())).collect(), 300);
+        ReverseLookup {
+            names: Arc::new(holder),
+            zones: Arc::new(private_zones()),
+            forwards: Arc::new(vec![]),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn should_return_ip_when_parse_reverse_given_arpa_names() {
+        assert_eq!(Some("192.168.1.2".parse().unwrap()), parse_reverse("2.1.168.192.in-addr.arpa"));
+        assert_eq!(Some("fd00::2".parse().unwrap()),
+                   parse_reverse("2.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.d.f.ip6.arpa"));
+        assert_eq!(None, parse_reverse("1.168.192.in-addr.arpa"));
+        assert_eq!(None, parse_reverse("2.1.168.300.in-addr.arpa"));
+        assert_eq!(None, parse_reverse("www.example.com"));
+    }
+
+    #[test]
+    fn should_match_private_zones_when_in_zone_given_reverse_names() {
+        let zones = private_zones();
+        let private = |name: &str| zones.iter().any(|z| in_zone(name, z));
+
+        assert!(private("2.1.168.192.in-addr.arpa"));
+        assert!(private("1.0.20.172.in-addr.arpa"));
+        assert!(private("10.in-addr.arpa"));
+        assert!(!private("1.0.32.172.in-addr.arpa"));
+        assert!(!private("8.8.8.8.in-addr.arpa"));
+        assert!(!private("110.in-addr.arpa"));
+    }
+
+    #[test]
+    fn should_return_local_names_when_answer_given_known_ip() {
+        let lookup = lookup(vec![("192.168.1.2", "nas.home"), ("1.2.3.4", "api.example.com")]);
+
+        let private = lookup.answer(&ptr_query("2.1.168.192.in-addr.arpa"), "2.1.168.192.in-addr.arpa").unwrap();
+        let public = lookup.answer(&ptr_query("4.3.2.1.in-addr.arpa"), "4.3.2.1.in-addr.arpa").unwrap();
+
+        assert_eq!((0, 1), (private.get_rcode(), private.get_answers().len()));
+        assert_eq!((0, 1), (public.get_rcode(), public.get_answers().len()));
+    }
+
+    #[test]
+    fn should_return_nxdomain_or_none_when_answer_given_unknown_ip() {
+        let lookup = lookup(vec![]);
+
+        let private = lookup.answer(&ptr_query("3.1.168.192.in-addr.arpa"), "3.1.168.192.in-addr.arpa").unwrap();
+        let public = lookup.answer(&ptr_query("8.8.8.8.in-addr.arpa"), "8.8.8.8.in-addr.arpa");
+
+        assert_eq!((3, 0), (private.get_rcode(), private.get_answers().len()));
+        assert!(public.is_none());
+    }
+
+    //本地模拟一个内网的dns服务器, 对PTR查询回答nas.home
+    async fn ptr_stub() -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            let (_, from) = socket.recv_from(&mut buf).await.unwrap();
+            let mut end = 12;
+            while buf[end] != 0 {
+                end += buf[end] as usize + 1;
+            }
+            let mut answer = buf[..end + 5].to_vec();
+            answer[2..12].copy_from_slice(&[0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0]);
+            answer.extend_from_slice(&[0xc0, 0x0c, 0, 12, 0, 1, 0, 0, 0, 60, 0, 10]);
+            answer.extend_from_slice(&[3, b'n', b'a', b's', 4, b'h', b'o', b'm', b'e', 0]);
+            socket.send_to(&answer, from).await.unwrap();
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn should_relay_answer_when_handle_given_forwarded_ptr_query() {
+        let server_group = ServerGroup::from(vec![ptr_stub().await], 1, 0, 0).await.unwrap();
+        let mut lookup = lookup(vec![]);
+        lookup.forwards = Arc::new(vec![("1.168.192.in-addr.arpa".into(), Arc::new(server_group))]);
+        let mut query = ptr_query("2.1.168.192.in-addr.arpa");
+        query.set_id(1234);
+
+        let answer = lookup.handle(Clain::new(), query).await.unwrap();
+        let bytes = answer.to_bytes();
+
+        assert_eq!(1234, answer.get_id());
+        assert_eq!([0x04, 0xd2], bytes[0..2]);
+        assert_eq!([0, 1], bytes[6..8]);
+        assert!(bytes.ends_with(&[3, b'n', b'a', b's', 4, b'h', b'o', b'm', b'e', 0]));
+    }
+}
@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+use crate::handler::{Clain, Handler, Resolver, cname_answer};
+use crate::handler::client_group::ClientPolicies;
+use crate::system::Result;
+use crate::protocol::{DnsAnswer, DnsQuery};
+use regex::Regex;
+use std::sync::Arc;
+
+//搜索引擎的域名和对应的安全搜索域名
+const SAFE_SEARCH_RULES: [(&str, &str); 4] = [
+    (r"^(www\.)?google\.(com|[a-z]{2}|co\.[a-z]{2}|com\.[a-z]{2})$", "forcesafesearch.google.com"),
+    (r"^(www\.)?bing\.com$", "strict.bing.com"),
+    (r"^(www\.|start\.)?duckduckgo\.com$", "safe.duckduckgo.com"),
+    (r"^((www|m)\.youtube\.com|youtubei?\.googleapis\.com|www\.youtube-nocookie\.com)$",
+     "restrict.youtube.com"),
+];
+
+//开启了安全搜索的客户端查询搜索引擎的时候, 返回指向安全搜索域名的cname和它同类型的记录
+#[derive(Clone)]
+pub struct SafeSearch {
+    policies: Arc<ClientPolicies>,
+    rules: Arc<Vec<(Regex, String)>>,
+    resolver: Resolver,
+}
+
+impl SafeSearch {
+    pub fn new(policies: Arc<ClientPolicies>, resolver: Resolver) -> Self {
+        SafeSearch {
+            policies,
+            rules: Arc::new(safe_search_rules()),
+            resolver,
+        }
+    }
+}
+
+fn safe_search_rules() -> Vec<(Regex, String)> {
+    SAFE_SEARCH_RULES.iter()
+        .map(|(pattern, target)| (Regex::new(pattern).unwrap(), String::from(*target)))
+        .collect()
+}
+
+fn find_target<'a>(rules: &'a [(Regex, String)], name: &String) -> Option<&'a String> {
+    let name = name.to_lowercase();
+    rules.iter().find(|(regex, _)| regex.is_match(&name)).map(|(_, target)| target)
+}
+
+#[async_trait]
+impl Handler for SafeSearch {
+    async fn handle(&self, clain: Clain, query: DnsQuery) -> Result<DnsAnswer> {
+        if !self.policies.get(query.get_client()).safe_search {
+            return clain.next(query).await;
+        }
+        let target = match find_target(&self.rules, query.get_name()) {
+            Some(target) => target,
+            None => return clain.next(query).await,
+        };
+        debug!("{} 改写成安全搜索 {}", query.get_name(), target);
+        let target_answer = self.resolver.resolve(query.redirect(target)).await?;
+        Ok(cname_answer(&query, target, Some(&target_answer)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::cname_answer;
+    use crate::handler::safe_search::{find_target, safe_search_rules};
+    use crate::handler::tests::{query_buf, test_context};
+    use crate::protocol::{DnsAnswer, DnsQuery, LocalAnswer, LocalRecord};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    #[test]
+    fn should_return_target_when_find_target_given_search_engine_domains() {
+        let rules = safe_search_rules();
+        let find = |name: &str| find_target(&rules, &name.into()).map(|t| t.as_str());
+
+        assert_eq!(Some("forcesafesearch.google.com"), find("www.google.com"));
+        assert_eq!(Some("forcesafesearch.google.com"), find("www.google.co.jp"));
+        assert_eq!(Some("forcesafesearch.google.com"), find("google.de"));
+        assert_eq!(Some("strict.bing.com"), find("WWW.Bing.com"));
+        assert_eq!(Some("safe.duckduckgo.com"), find("duckduckgo.com"));
+        assert_eq!(Some("restrict.youtube.com"), find("m.youtube.com"));
+        assert_eq!(None, find("mail.google.com"));
+        assert_eq!(None, find("forcesafesearch.google.com"));
+    }
+
+    #[tokio::test]
+    async fn should_return_cname_and_aaaa_records_when_handle_query_given_aaaa_query() {
+        let (context, filter, _) = test_context("easydns_safe_search_aaaa", "safe-search = true").await;
+        let client: SocketAddr = "192.168.1.20:5353".parse().unwrap();
+
+        let answer = context.handle_query(query_buf("www.bing.com", 28), client).await.unwrap();
+
+        std::fs::remove_file(&filter).unwrap();
+        let target = String::from("strict.bing.com");
+        let expected = vec![LocalRecord::cname("www.bing.com".into(), 60, &target),
+                            LocalRecord::ip(target.clone(), 60, &"fd00::1234".parse().unwrap())];
+        assert_eq!(expected, *answer.as_any().downcast_ref::<LocalAnswer>().unwrap().get_answers());
+    }
+
+    #[test]
+    fn should_return_cname_and_a_records_when_answer_given_target_ips() {
+        let query = DnsQuery::from("www.google.com");
+        let target = String::from("forcesafesearch.google.com");
+        let mut local = LocalAnswer::new(&DnsQuery::from(target.as_str()), 0);
+        local.add_answer(LocalRecord::ip(target.clone(), 120, &IpAddr::V4(Ipv4Addr::new(216, 239, 38, 120))));
+        let bytes = DnsAnswer::from(local).to_bytes();
+        let mut buf = [0u8; 512];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        let target_answer = DnsAnswer::from(buf);
+
+        let answer = cname_answer(&query, &target, Some(&target_answer));
+
+        assert_eq!(vec![LocalRecord::cname("www.google.com".into(), 120, &target),
+                        LocalRecord::ip(target.clone(), 120, &IpAddr::V4(Ipv4Addr::new(216, 239, 38, 120)))],
+                   *answer.get_answers());
+    }
+
+    #[test]
+    fn should_return_only_cname_when_answer_given_no_target_ip() {
+        let query = DnsQuery::from("www.bing.com");
+        let target = String::from("strict.bing.com");
+
+        let answer = cname_answer(&query, &target, None);
+
+        assert_eq!(vec![LocalRecord::cname("www.bing.com".into(), 300, &target)], *answer.get_answers());
+    }
+}
@@ -1,18 +1,20 @@
 use crate::system::Result;
 use async_trait::async_trait;
 use crate::handler::server_group::query_executor::QueryExecutor;
-use crate::handler::server_group::ServerSender;
+use crate::handler::server_group::{ServerSender, PollutedServers};
+use std::sync::Arc;
 use crate::protocol::{DnsAnswer, Ipv4Answer, FailureAnswer, DnsQuery};
 
 pub struct CombineServerSender {
     executor: QueryExecutor,
     servers: Vec<String>,
+    polluted: Arc<PollutedServers>,
 }
 
 #[async_trait]
 impl ServerSender for CombineServerSender {
     async fn send(&self, query: DnsQuery) -> Result<DnsAnswer> {
-        let servers = &self.servers;
+        let servers = self.polluted.available(&self.servers);
         let mut future_vec = Vec::with_capacity(servers.len());
         for address in servers.iter() {
             future_vec.push(self.executor.exec(address.as_str(), query.clone()));
@@ -39,10 +41,12 @@ impl ServerSender for CombineServerSender {
 }
 
 impl CombineServerSender {
-    pub fn from(executor: QueryExecutor, servers: Vec<String>) -> Self {
+    pub fn from(executor: QueryExecutor, servers: Vec<String>,
+                polluted: Arc<PollutedServers>) -> Self {
         CombineServerSender {
             executor,
             servers,
+            polluted,
         }
     }
 }
\ No newline at end of file
@@ -6,19 +6,24 @@ use async_trait::async_trait;
 use futures_util::future::select_all;
 use tokio::time::interval;
 use crate::handler::server_group::query_executor::QueryExecutor;
-use crate::handler::server_group::ServerSender;
+use crate::handler::server_group::{ServerSender, PollutedServers};
 use crate::protocol::{DnsAnswer, DnsQuery};
 
 pub struct FastServerSender {
     executor: Arc<QueryExecutor>,
     servers: Arc<Vec<String>>,
     fast_server: Arc<Mutex<String>>,
+    polluted: Arc<PollutedServers>,
 }
 
 #[async_trait]
 impl ServerSender for FastServerSender {
     async fn send(&self, query: DnsQuery) -> Result<DnsAnswer> {
-        let address = self.fast_server.lock().unwrap().clone();
+        let mut address = self.fast_server.lock().unwrap().clone();
+        //最快的服务器被污染了就换一个
+        if self.polluted.is_polluted(&address) {
+            address = self.polluted.available(&self.servers)[0].clone();
+        }
         self.executor.exec(address.as_str(), query).await
     }
 }
@@ -28,6 +33,7 @@ impl FastServerSender {
         query_executor: QueryExecutor,
         servers: Vec<String>,
         duration_secs: u64,
+        polluted: Arc<PollutedServers>,
     ) -> Self {
         let executor = Arc::new(query_executor);
         let cloned_executor = executor.clone();
@@ -39,6 +45,7 @@ impl FastServerSender {
             executor: cloned_executor,
             servers: cloned_servers,
             fast_server: cloned_fast_server,
+            polluted: polluted.clone(),
         };
 
         tokio::spawn(async move {
@@ -56,6 +63,7 @@ impl FastServerSender {
             executor,
             servers: arc_servers,
             fast_server,
+            polluted,
         }
     }
 
@@ -3,8 +3,10 @@ mod prefer_server_sender;
 mod combine_server_sender;
 mod query_executor;
 
-use crate::system::Result;
+use crate::system::{Result, get_now};
 use async_trait::async_trait;
+use dashmap::DashMap;
+use std::sync::Arc;
 use crate::handler::server_group::fast_server_sender::FastServerSender;
 use crate::handler::server_group::prefer_server_sender::PreferServerSender;
 use crate::handler::server_group::combine_server_sender::CombineServerSender;
@@ -18,23 +20,73 @@ pub trait ServerSender: Sync + Send {
 
 pub struct ServerGroup {
     server_sender: Box<dyn ServerSender>,
+    polluted: Arc<PollutedServers>,
 }
 
 impl ServerGroup {
-    pub async fn from(servers: Vec<String>, strategy: usize, duration_secs: u64) -> Result<Self> {
+    pub async fn from(servers: Vec<String>, strategy: usize, duration_secs: u64,
+                      polluted_duration_ms: u128) -> Result<Self> {
         let query_executor = QueryExecutor::create().await?;
+        let polluted = Arc::new(PollutedServers::new(polluted_duration_ms));
         let server_sender: Box<dyn ServerSender> = match strategy {
-            0 => Box::new(FastServerSender::from(query_executor, servers, duration_secs)),
-            1 => Box::new(PreferServerSender::from(query_executor, servers)),
-            2 => Box::new(CombineServerSender::from(query_executor, servers)),
+            0 => Box::new(FastServerSender::from(query_executor, servers, duration_secs,
+                                                 polluted.clone())),
+            1 => Box::new(PreferServerSender::from(query_executor, servers, polluted.clone())),
+            2 => Box::new(CombineServerSender::from(query_executor, servers, polluted.clone())),
             _ => panic!("不支持的server strategy类型！"),
         };
         Ok(ServerGroup {
             server_sender,
+            polluted,
         })
     }
 
     pub async fn send_query(&self, query: DnsQuery) -> Result<DnsAnswer> {
         self.server_sender.send(query).await
     }
+
+    pub fn mark_polluted(&self, server: &String) {
+        self.polluted.mark(server);
+    }
+}
+
+//被标记为污染的上游服务器, 在一段时间内不会再发送查询
+pub struct PollutedServers {
+    map: DashMap<String, u128>,
+    duration_ms: u128,
+}
+
+impl PollutedServers {
+    fn new(duration_ms: u128) -> Self {
+        PollutedServers {
+            map: DashMap::new(),
+            duration_ms,
+        }
+    }
+
+    fn mark(&self, server: &String) {
+        warn!("上游服务器{}返回了被污染的应答, {}ms内不会再使用", server, self.duration_ms);
+        self.map.insert(server.clone(), get_now() + self.duration_ms);
+    }
+
+    fn is_polluted(&self, server: &String) -> bool {
+        match self.map.get(server).map(|e| *e.value()) {
+            Some(until) if until > get_now() => true,
+            Some(_) => {
+                self.map.remove(server);
+                false
+            }
+            None => false,
+        }
+    }
+
+    //没有被污染的服务器, 全部都被污染了的话就还是用全部的服务器
+    fn available<'a>(&self, servers: &'a [String]) -> Vec<&'a String> {
+        let available: Vec<&String> = servers.iter().filter(|s| !self.is_polluted(s)).collect();
+        if available.is_empty() {
+            servers.iter().collect()
+        } else {
+            available
+        }
+    }
 }
@@ -3,18 +3,20 @@ use crate::system::Result;
 use futures_util::future::select_all;
 use futures_util::FutureExt;
 use crate::handler::server_group::query_executor::QueryExecutor;
-use crate::handler::server_group::ServerSender;
+use crate::handler::server_group::{ServerSender, PollutedServers};
+use std::sync::Arc;
 use crate::protocol::{DnsAnswer, DnsQuery};
 
 pub struct PreferServerSender {
     executor: QueryExecutor,
     servers: Vec<String>,
+    polluted: Arc<PollutedServers>,
 }
 
 #[async_trait]
 impl ServerSender for PreferServerSender {
     async fn send(&self, query: DnsQuery) -> Result<DnsAnswer> {
-        let servers = &self.servers;
+        let servers = self.polluted.available(&self.servers);
         let mut future_vec = Vec::with_capacity(servers.len());
         for address in servers.iter() {
             future_vec.push(self.executor.exec(address.as_str(), query.clone()).boxed());
@@ -26,10 +28,12 @@ impl ServerSender for PreferServerSender {
 }
 
 impl PreferServerSender {
-    pub fn from(executor: QueryExecutor, servers: Vec<String>) -> Self {
+    pub fn from(executor: QueryExecutor, servers: Vec<String>,
+                polluted: Arc<PollutedServers>) -> Self {
         PreferServerSender {
             executor,
             servers,
+            polluted,
         }
     }
 }
\ No newline at end of file
@@ -61,6 +61,7 @@ impl QueryExecutor {
         };
         self.reg_table.remove(&next_id);
         answer.set_id(client_query_id);
+        answer.set_server(address.to_string());
         Ok(answer)
     }
 
@@ -4,6 +4,7 @@
 use std::sync::Arc;
 
 use simple_logger::SimpleLogger;
+use tokio::signal::unix::{signal, SignalKind};
 
 use crate::handler::*;
 use crate::system::{Result};
@@ -17,6 +18,8 @@ mod cache;
 mod client;
 mod cursor;
 mod protocol;
+mod resource;
+mod zone;
 
 #[macro_use]
 extern crate log;
@@ -32,6 +35,7 @@ async fn main() -> Result<()> {
     system::setup_log_level(&config)?;
     let client = Arc::new(ClientSocket::new(config.port).await?);
     let handler = Arc::new(HandlerContext::from(config).await?);
+    let mut terminate = signal(SignalKind::terminate())?;
     //主循环
     loop {
         tokio::select! {
@@ -40,7 +44,7 @@ async fn main() -> Result<()> {
                 let arc_client = client.clone();
                 let arc_handler = handler.clone();
                 tokio::spawn(async move {
-                    let answer = match arc_handler.handle_query(query_buf).await {
+                    let answer = match arc_handler.handle_query(query_buf, src).await {
                         Ok(answer) => answer,
                         Err(e) => {
                             error!("Handle query task error: {:?}", e);
@@ -57,7 +61,14 @@ async fn main() -> Result<()> {
             _ = tokio::signal::ctrl_c() => {
                 break;
             }
+            //路由器的watchdog或者init脚本是用SIGTERM来停止进程的
+            _ = terminate.recv() => {
+                info!("收到SIGTERM信号, 准备退出");
+                break;
+            }
         }
     }
+    info!("filter stats:\n{}", handler.filter_report());
+    handler.persist_cache().await;
     Ok(())
 }
@@ -6,10 +6,14 @@ use std::any::Any;
 use crate::protocol::{DnsAnswer};
 use std::net::Ipv4Addr;
 use crate::protocol::basic::{BasicData, Builder};
+use crate::protocol::ClientSubnet;
 
 pub struct Ipv4Answer {
     data: BasicData,
     resources: Vec<Ipv4Resource>,
+    client_subnet: Option<ClientSubnet>,
+    cnames: Vec<String>,
+    server: Option<String>,
 }
 
 impl Display for Ipv4Answer {
@@ -49,6 +53,30 @@ impl Answer for Ipv4Answer {
     fn get_id(&self) -> u16 {
         self.data.get_id()
     }
+
+    fn get_client_subnet(&self) -> Option<&ClientSubnet> {
+        self.client_subnet.as_ref()
+    }
+
+    fn set_client_subnet(&mut self, subnet: ClientSubnet) {
+        self.client_subnet = Some(subnet);
+    }
+
+    fn get_cnames(&self) -> &[String] {
+        &self.cnames
+    }
+
+    fn set_cnames(&mut self, cnames: Vec<String>) {
+        self.cnames = cnames;
+    }
+
+    fn get_server(&self) -> Option<&String> {
+        self.server.as_ref()
+    }
+
+    fn set_server(&mut self, server: String) {
+        self.server = Some(server);
+    }
 }
 
 impl Ipv4Answer {
@@ -60,6 +88,9 @@ impl Ipv4Answer {
         Ipv4Answer {
             data,
             resources,
+            client_subnet: None,
+            cnames: vec![],
+            server: None,
         }
     }
 
@@ -88,6 +119,9 @@ impl Ipv4Answer {
         Ipv4Answer {
             data,
             resources: vec![],
+            client_subnet: None,
+            cnames: vec![],
+            server: None,
         }
     }
 
@@ -101,6 +135,11 @@ impl Ipv4Answer {
         }).collect()
     }
 
+    pub fn retain_ips(&mut self, f: impl Fn(&Ipv4Addr) -> bool) {
+        self.resources.retain(|r| f(&r.data));
+        self.data.set_answer_count(self.resources.len() as u16);
+    }
+
     pub fn retain_ip(&mut self, ip: &Ipv4Addr) {
         self.resources.retain(|r| {
             r.data.eq(ip)
@@ -112,6 +151,12 @@ impl Ipv4Answer {
         self.data.get_name()
     }
 
+    //换成另一个域名的应答, ip和ttl不变
+    pub fn set_name(&mut self, name: String) {
+        self.resources.iter_mut().for_each(|r| r.set_name(name.clone()));
+        self.data.set_name(name);
+    }
+
     pub fn get_ttl(&self) -> u32 {
         self.resources[0].get_ttl()
     }
@@ -132,6 +177,9 @@ impl From<&IpCacheRecord> for Ipv4Answer {
         Ipv4Answer {
             data,
             resources: vec![resource],
+            client_subnet: None,
+            cnames: record.cnames.clone(),
+            server: None,
         }
     }
 }
\ No newline at end of file
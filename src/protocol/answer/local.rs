@@ -0,0 +1,261 @@
+use crate::protocol::answer::Answer;
+use crate::protocol::answer::resource::SoaResource;
+use crate::cache::CacheRecord;
+use std::fmt::{Display, Formatter};
+use std::any::Any;
+use std::net::IpAddr;
+use crate::protocol::basic::{BasicData, Builder};
+use crate::protocol::{DnsQuery, wrap_name};
+
+//本地合成应答的时候cname最多追多少层, 防止配置成环
+pub const MAX_CNAME_DEPTH: usize = 8;
+
+//本地生成的记录, data是已经编码好的数据部分
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LocalRecord {
+    pub name: String,
+    pub _type: u16,
+    pub ttl: u32,
+    pub data: Vec<u8>,
+}
+
+impl LocalRecord {
+    pub fn ip(name: String, ttl: u32, ip: &IpAddr) -> Self {
+        let (_type, data) = match ip {
+            IpAddr::V4(ip) => (1, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (28, ip.octets().to_vec()),
+        };
+        LocalRecord {
+            name,
+            _type,
+            ttl,
+            data,
+        }
+    }
+
+    pub fn cname(name: String, ttl: u32, target: &String) -> Self {
+        Self::with_name(name, 5, ttl, target)
+    }
+
+    //NS, CNAME, PTR这种数据只有一个域名的记录
+    pub fn with_name(name: String, _type: u16, ttl: u32, target: &String) -> Self {
+        LocalRecord {
+            name,
+            _type,
+            ttl,
+            data: wrap_name(target),
+        }
+    }
+
+    pub fn mx(name: String, ttl: u32, preference: u16, exchange: &String) -> Self {
+        let mut data = preference.to_be_bytes().to_vec();
+        data.extend(wrap_name(exchange));
+        LocalRecord {
+            name,
+            _type: 15,
+            ttl,
+            data,
+        }
+    }
+
+    pub fn srv(name: String, ttl: u32, priority: u16, weight: u16, port: u16, target: &String) -> Self {
+        let mut data = Vec::new();
+        data.extend(&priority.to_be_bytes());
+        data.extend(&weight.to_be_bytes());
+        data.extend(&port.to_be_bytes());
+        data.extend(wrap_name(target));
+        LocalRecord {
+            name,
+            _type: 33,
+            ttl,
+            data,
+        }
+    }
+
+    //values是serial, refresh, retry, expire, minimum
+    pub fn soa_with(name: String, ttl: u32, mname: &String, rname: &String, values: [u32; 5]) -> Self {
+        let mut data = wrap_name(mname);
+        data.extend(wrap_name(rname));
+        values.iter().for_each(|v| data.extend(&v.to_be_bytes()));
+        LocalRecord {
+            name,
+            _type: 6,
+            ttl,
+            data,
+        }
+    }
+
+    //每一段超过255字节的文本再分成多段
+    pub fn txt(name: String, ttl: u32, texts: &[String]) -> Self {
+        let mut data = Vec::new();
+        texts.iter().for_each(|text| {
+            text.as_bytes().chunks(255).for_each(|chunk| {
+                data.push(chunk.len() as u8);
+                data.extend_from_slice(chunk);
+            });
+        });
+        if data.is_empty() {
+            data.push(0);
+        }
+        LocalRecord {
+            name,
+            _type: 16,
+            ttl,
+            data,
+        }
+    }
+
+    pub fn soa(name: String, ttl: u32) -> Self {
+        LocalRecord {
+            name,
+            _type: 6,
+            ttl,
+            data: SoaResource::default_data(),
+        }
+    }
+}
+
+impl From<&LocalRecord> for Vec<u8> {
+    fn from(r: &LocalRecord) -> Self {
+        let mut vec = wrap_name(&r.name);
+        vec.extend(&r._type.to_be_bytes());
+        vec.extend(&1u16.to_be_bytes());
+        vec.extend(&r.ttl.to_be_bytes());
+        vec.extend(&(r.data.len() as u16).to_be_bytes());
+        vec.extend(&r.data);
+        vec
+    }
+}
+
+//不经过上游服务器, 直接本地回答的应答, 不会进缓存
+pub struct LocalAnswer {
+    data: BasicData,
+    answers: Vec<LocalRecord>,
+    authorities: Vec<LocalRecord>,
+    blocked: bool,
+}
+
+impl Display for LocalAnswer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(LOCAL, {}, {}, {})", self.data.get_name(), self.get_rcode(), self.answers.len())
+    }
+}
+
+impl Answer for LocalAnswer {
+    fn to_cache(&self) -> Option<CacheRecord> {
+        None
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let data = &self.data;
+        let mut vec: Vec<u8> = data.into();
+        self.answers.iter().chain(self.authorities.iter()).for_each(|r| {
+            let record: Vec<u8> = r.into();
+            vec.extend(record);
+        });
+        vec
+    }
+
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut (dyn Any + Send + Sync) {
+        self
+    }
+
+    fn set_id(&mut self, id: u16) {
+        self.data.set_id(id);
+    }
+
+    fn get_id(&self) -> u16 {
+        self.data.get_id()
+    }
+
+    fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+}
+
+impl LocalAnswer {
+    //rcode: 0 正常, 3 域名不存在, 5 拒绝
+    pub fn new(query: &DnsQuery, rcode: u16) -> Self {
+        let data = Builder::new()
+            .id(query.get_id())
+            .name(query.get_name().clone())
+            ._type(query.get_type())
+            .flags(0x8180 | rcode)
+            .build();
+        LocalAnswer {
+            data,
+            answers: vec![],
+            authorities: vec![],
+            blocked: false,
+        }
+    }
+
+    pub fn set_blocked(&mut self) {
+        self.blocked = true;
+    }
+
+    pub fn add_answer(&mut self, record: LocalRecord) {
+        self.answers.push(record);
+        self.data.set_answer_count(self.answers.len() as u16);
+    }
+
+    pub fn add_authority(&mut self, record: LocalRecord) {
+        self.authorities.push(record);
+        self.data.set_authority_count(self.authorities.len() as u16);
+    }
+
+    //本地配置的记录是权威应答
+    pub fn set_authoritative(&mut self) {
+        self.data.set_flags(self.data.get_flags() | 0x0400);
+    }
+
+    pub fn get_rcode(&self) -> u16 {
+        self.data.get_flags() & 0x000F
+    }
+
+    pub fn get_answers(&self) -> &Vec<LocalRecord> {
+        &self.answers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{DnsQuery, DnsAnswer, LocalAnswer, LocalRecord};
+    use crate::protocol::answer::Answer;
+    use crate::system::AnswerBuf;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_parse_to_ip_answer_when_to_bytes_given_local_a_record() {
+        let query = DnsQuery::from("www.baidu.com");
+        let mut local = LocalAnswer::new(&query, 0);
+        local.add_answer(LocalRecord::ip("www.baidu.com".into(), 60,
+                                         &IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))));
+
+        let answer = DnsAnswer::from(to_buf(local.to_bytes()));
+
+        assert_eq!("(IP, www.baidu.com, 60, 1.2.3.4)", answer.to_string());
+        assert_eq!(query.get_id(), answer.get_id());
+    }
+
+    #[test]
+    fn should_parse_to_soa_answer_when_to_bytes_given_local_soa_authority() {
+        let query = DnsQuery::from("www.baidu.com");
+        let mut local = LocalAnswer::new(&query, 0);
+        local.add_authority(LocalRecord::soa("www.baidu.com".into(), 30));
+
+        let answer = DnsAnswer::from(to_buf(local.to_bytes()));
+
+        assert_eq!("(SOA, www.baidu.com, 30)", answer.to_string());
+    }
+
+    fn to_buf(bytes: Vec<u8>) -> AnswerBuf {
+        let mut buf = [0u8; 512];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        buf
+    }
+}
@@ -3,11 +3,13 @@ mod resource;
 mod no_such_name;
 mod soa;
 mod ipv4;
+mod local;
+mod raw;
 
 use crate::cache::CacheRecord;
 use crate::system::AnswerBuf;
 use crate::cursor::Cursor;
-use crate::protocol::answer::resource::{CnameResource, Ipv4Resource, SoaResource};
+use crate::protocol::answer::resource::{CnameResource, Ipv4Resource, SoaResource, Resource};
 use crate::protocol::answer::no_such_name::NoSuchNameAnswer;
 use std::fmt::{Display};
 use std::any::Any;
@@ -17,7 +19,10 @@ pub type DnsAnswer = Box<dyn Answer>;
 pub use ipv4::Ipv4Answer;
 pub use failure::FailureAnswer;
 pub use soa::SoaAnswer;
+pub use local::{LocalAnswer, LocalRecord, MAX_CNAME_DEPTH};
+pub use raw::RawAnswer;
 use crate::protocol::basic::BasicData;
+use crate::protocol::edns::{ClientSubnet, parse_additional};
 
 pub trait Answer: Display + Send + Sync {
     fn to_cache(&self) -> Option<CacheRecord>;
@@ -26,11 +31,31 @@ pub trait Answer: Display + Send + Sync {
     fn as_mut_any(&mut self) -> &mut (dyn Any + Send + Sync);
     fn set_id(&mut self, id: u16);
     fn get_id(&self) -> u16;
+    fn get_client_subnet(&self) -> Option<&ClientSubnet> {
+        None
+    }
+    fn set_client_subnet(&mut self, _subnet: ClientSubnet) {}
+    //应答里面cname链上的所有域名
+    fn get_cnames(&self) -> &[String] {
+        &[]
+    }
+    fn set_cnames(&mut self, _cnames: Vec<String>) {}
+    //返回这个应答的上游服务器
+    fn get_server(&self) -> Option<&String> {
+        None
+    }
+    fn set_server(&mut self, _server: String) {}
+    //被过滤规则拦截之后返回的应答, 统计的时候用
+    fn is_blocked(&self) -> bool {
+        false
+    }
 }
 
 impl From<AnswerBuf> for DnsAnswer {
     fn from(buf: AnswerBuf) -> Self {
         // info!("buf: {:?}", &buf[0..buf.len()]);
+        let additional_count = u16::from_be_bytes([buf[10], buf[11]]);
+        let raw = buf;
         let cursor = Cursor::form(buf.into());
         let data = BasicData::from(&cursor);
         if data.get_flags() == 0x8182 {
@@ -43,17 +68,23 @@ impl From<AnswerBuf> for DnsAnswer {
             return NoSuchNameAnswer::from(data).into();
         }
         let mut ipv4_records = Vec::new();
+        let mut cnames = Vec::new();
+        //有其他类型的记录就把整个应答原样转发
+        let mut other_records = false;
+        let mut records = Vec::new();
         (0..data.get_answer_count() as usize).into_iter().for_each(|_| {
             let r_data = resource::BasicData::from(&cursor);
+            records.push(raw::decode_record(&r_data, &cursor));
             if r_data.get_type() == 5 {
-                // cname记录 目前的处理是移除
-                let _resource = CnameResource::create(r_data, &cursor);
+                // cname记录 只保留指向的域名, 用来检查是否被拦截
+                let resource = CnameResource::create(r_data, &cursor);
+                cnames.push(resource.get_data().clone());
             } else if r_data.get_type() == 1 {
                 // a记录
                 ipv4_records.push(Ipv4Resource::create(r_data, &cursor));
             } else {
-                panic!("不支持的应答资源记录类型: name = {}, type = {}",
-                       r_data.get_name(), r_data.get_type())
+                cursor.move_to(r_data.get_data_len() as usize);
+                other_records = true;
             };
         });
         let mut soa_records = Vec::new();
@@ -62,17 +93,23 @@ impl From<AnswerBuf> for DnsAnswer {
             if r_data.get_type() == 6 {
                 soa_records.push(SoaResource::create(r_data, &cursor));
             } else {
-                panic!("不支持的认证资源记录类型: name = {}, type = {}",
-                       r_data.get_name(), r_data.get_type())
+                //NS这些认证记录用不到
+                cursor.move_to(r_data.get_data_len() as usize);
             }
         });
-        if !ipv4_records.is_empty() {
-            return Ipv4Answer::create(data, ipv4_records).into();
-        }
-        if !soa_records.is_empty() {
-            return SoaAnswer::create(data, soa_records.remove(0)).into();
+        let subnet = parse_additional(&cursor, additional_count);
+        let mut answer: DnsAnswer = if !other_records && !ipv4_records.is_empty() {
+            Ipv4Answer::create(data, ipv4_records).into()
+        } else if !other_records && !soa_records.is_empty() {
+            SoaAnswer::create(data, soa_records.remove(0)).into()
+        } else {
+            RawAnswer::create(data, raw[..cursor.get_current_index()].to_vec(), records).into()
+        };
+        if let Some(subnet) = subnet {
+            answer.set_client_subnet(subnet);
         }
-        unreachable!()
+        answer.set_cnames(cnames);
+        answer
     }
 }
 
@@ -94,8 +131,44 @@ impl From<SoaAnswer> for DnsAnswer {
     }
 }
 
+impl From<LocalAnswer> for DnsAnswer {
+    fn from(f: LocalAnswer) -> Self {
+        Box::new(f)
+    }
+}
+
+impl From<RawAnswer> for DnsAnswer {
+    fn from(f: RawAnswer) -> Self {
+        Box::new(f)
+    }
+}
+
 impl From<Ipv4Answer> for DnsAnswer {
     fn from(f: Ipv4Answer) -> Self {
         Box::new(f)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{DnsQuery, DnsAnswer, LocalAnswer, LocalRecord};
+    use crate::protocol::answer::Answer;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_keep_cname_targets_when_parse_given_answer_with_cname_chain() {
+        let query = DnsQuery::from("www.a.com");
+        let mut local = LocalAnswer::new(&query, 0);
+        local.add_answer(LocalRecord::cname("www.a.com".into(), 60, &"b.com".into()));
+        local.add_answer(LocalRecord::cname("b.com".into(), 60, &"c.tracker.com".into()));
+        local.add_answer(LocalRecord::ip("c.tracker.com".into(), 60,
+                                         &IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))));
+        let mut buf = [0u8; 512];
+        let bytes = local.to_bytes();
+        buf[..bytes.len()].copy_from_slice(&bytes);
+
+        let answer = DnsAnswer::from(buf);
+
+        let expected: Vec<String> = vec!["b.com".into(), "c.tracker.com".into()];
+        assert_eq!(expected.as_slice(), answer.get_cnames());
+    }
+}
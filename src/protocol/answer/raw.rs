@@ -0,0 +1,113 @@
+use crate::protocol::answer::Answer;
+use crate::cache::CacheRecord;
+use std::fmt::{Display, Formatter};
+use std::any::Any;
+use crate::protocol::basic::BasicData;
+use crate::protocol::answer::{LocalRecord, resource};
+use crate::protocol::unzip_domain;
+use crate::cursor::Cursor;
+
+//有A和CNAME以外的记录(PTR, AAAA, MX这些)的应答, 不解析也不进缓存, 原样转发给客户端
+pub struct RawAnswer {
+    data: BasicData,
+    bytes: Vec<u8>,
+    //应答部分的记录, 改写和安全搜索带上目标域名的记录时用
+    answers: Vec<LocalRecord>,
+    cnames: Vec<String>,
+    server: Option<String>,
+}
+
+impl Display for RawAnswer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(RAW, {}, {}, {}字节)", self.data.get_name(), self.data.get_type(), self.bytes.len())
+    }
+}
+
+impl Answer for RawAnswer {
+    fn to_cache(&self) -> Option<CacheRecord> {
+        None
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+
+    fn as_mut_any(&mut self) -> &mut (dyn Any + Send + Sync) {
+        self
+    }
+
+    fn set_id(&mut self, id: u16) {
+        self.data.set_id(id);
+        self.bytes[0..2].copy_from_slice(&id.to_be_bytes());
+    }
+
+    fn get_id(&self) -> u16 {
+        self.data.get_id()
+    }
+
+    fn get_cnames(&self) -> &[String] {
+        &self.cnames
+    }
+
+    fn set_cnames(&mut self, cnames: Vec<String>) {
+        self.cnames = cnames;
+    }
+
+    fn get_server(&self) -> Option<&String> {
+        self.server.as_ref()
+    }
+
+    fn set_server(&mut self, server: String) {
+        self.server = Some(server);
+    }
+}
+
+impl RawAnswer {
+    //bytes是上游返回的整个报文
+    pub fn create(data: BasicData, bytes: Vec<u8>, answers: Vec<LocalRecord>) -> Self {
+        RawAnswer {
+            data,
+            bytes,
+            answers,
+            cnames: vec![],
+            server: None,
+        }
+    }
+
+    pub fn get_answers(&self) -> &Vec<LocalRecord> {
+        &self.answers
+    }
+}
+
+//记录的数据部分转成本地记录, 数据里面压缩过的域名要解压, 不移动cursor
+pub fn decode_record(r_data: &resource::BasicData, cursor: &Cursor<u8>) -> LocalRecord {
+    let name = r_data.get_name().clone();
+    let (_type, ttl) = (r_data.get_type(), r_data.get_ttl());
+    let mut record = None;
+    cursor.tmp_at(cursor.get_current_index(), |cursor| {
+        record = Some(match _type {
+            2 | 5 | 12 => LocalRecord::with_name(name.clone(), _type, ttl, &unzip_domain(cursor)),
+            15 => {
+                let preference = u16::from_be_bytes(cursor.take_bytes());
+                LocalRecord::mx(name.clone(), ttl, preference, &unzip_domain(cursor))
+            }
+            33 => {
+                let priority = u16::from_be_bytes(cursor.take_bytes());
+                let weight = u16::from_be_bytes(cursor.take_bytes());
+                let port = u16::from_be_bytes(cursor.take_bytes());
+                LocalRecord::srv(name.clone(), ttl, priority, weight, port, &unzip_domain(cursor))
+            }
+            _ => LocalRecord {
+                name: name.clone(),
+                _type,
+                ttl,
+                data: cursor.take_slice(r_data.get_data_len() as usize).to_vec(),
+            },
+        });
+    });
+    record.unwrap()
+}
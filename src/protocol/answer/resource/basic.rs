@@ -48,6 +48,10 @@ impl BasicData {
         self.question._type
     }
 
+    pub fn get_data_len(&self) -> u16 {
+        self.data_len
+    }
+
     pub fn set_data_len(&mut self, len: u16) {
         self.data_len = len;
     }
@@ -61,6 +61,12 @@ impl SoaResource {
     pub fn set_name(&mut self, name: String) {
         self.basic.set_name(name)
     }
+
+    //默认soa记录的数据部分
+    pub fn default_data() -> Vec<u8> {
+        let soa = &Soa::default();
+        soa.into()
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
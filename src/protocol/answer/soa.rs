@@ -4,11 +4,14 @@ use crate::protocol::answer::resource::{SoaResource, Resource};
 use std::fmt::{Display, Formatter};
 use std::any::Any;
 use crate::protocol::basic::{BasicData, Builder};
+use crate::protocol::ClientSubnet;
 use crate::system::get_now;
 
 pub struct SoaAnswer {
     data: BasicData,
     resource: SoaResource,
+    client_subnet: Option<ClientSubnet>,
+    cnames: Vec<String>,
 }
 
 impl Display for SoaAnswer {
@@ -29,6 +32,8 @@ impl From<&SoaCacheRecord> for SoaAnswer {
         SoaAnswer {
             data,
             resource,
+            client_subnet: None,
+            cnames: vec![],
         }
     }
 }
@@ -62,6 +67,22 @@ impl Answer for SoaAnswer {
     fn get_id(&self) -> u16 {
         self.data.get_id()
     }
+
+    fn get_client_subnet(&self) -> Option<&ClientSubnet> {
+        self.client_subnet.as_ref()
+    }
+
+    fn set_client_subnet(&mut self, subnet: ClientSubnet) {
+        self.client_subnet = Some(subnet);
+    }
+
+    fn get_cnames(&self) -> &[String] {
+        &self.cnames
+    }
+
+    fn set_cnames(&mut self, cnames: Vec<String>) {
+        self.cnames = cnames;
+    }
 }
 
 impl SoaAnswer {
@@ -71,19 +92,8 @@ impl SoaAnswer {
         SoaAnswer {
             data,
             resource,
-        }
-    }
-
-    pub fn default_soa(id: u16, name: String) -> Self {
-        let data = Builder::new()
-            .id(id)
-            .name(name.clone())
-            .flags(0x8180)
-            .authority(1)
-            .build();
-        SoaAnswer {
-            data,
-            resource: SoaResource::new_with_default_soa(name, 600),
+            client_subnet: None,
+            cnames: vec![],
         }
     }
 
@@ -38,9 +38,18 @@ impl BasicData {
     pub fn get_flags(&self) -> u16 {
         self.header.flags
     }
+    pub fn set_flags(&mut self, flags: u16) {
+        self.header.flags = flags
+    }
     pub fn get_name(&self) -> &String {
         &self.question.name
     }
+    pub fn set_name(&mut self, name: String) {
+        self.question.name = name
+    }
+    pub fn get_type(&self) -> u16 {
+        self.question._type
+    }
 
     fn new() -> Self {
         let mut header = Header::new();
@@ -101,6 +110,14 @@ impl Builder {
         self
     }
 
+    pub fn _type(mut self, _type: u16) -> Self {
+        self.data.as_mut().map(|e| {
+            e.question._type = _type;
+            e
+        });
+        self
+    }
+
     pub fn name(mut self, name: String) -> Self {
         self.data.as_mut().map(|e| {
             e.question.name = name;
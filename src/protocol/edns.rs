@@ -0,0 +1,200 @@
+use crate::cursor::Cursor;
+use crate::protocol::unzip_domain;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const OPT_TYPE: u16 = 41;
+const ECS_CODE: u16 = 8;
+const UDP_PAYLOAD_SIZE: u16 = 512;
+
+//EDNS Client Subnet, RFC 7871
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ClientSubnet {
+    pub source_prefix: u8,
+    pub scope_prefix: u8,
+    pub address: IpAddr,
+}
+
+impl ClientSubnet {
+    fn from(data: &[u8]) -> Option<Self> {
+        if data.len() < 4 {
+            return None;
+        }
+        let family = u16::from_be_bytes([data[0], data[1]]);
+        let source_prefix = data[2];
+        let scope_prefix = data[3];
+        let address_bytes = &data[4..];
+        let address = match family {
+            1 if source_prefix <= 32 && address_bytes.len() <= 4 => {
+                let mut octets = [0u8; 4];
+                octets[..address_bytes.len()].copy_from_slice(address_bytes);
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            2 if source_prefix <= 128 && address_bytes.len() <= 16 => {
+                let mut octets = [0u8; 16];
+                octets[..address_bytes.len()].copy_from_slice(address_bytes);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return None,
+        };
+        Some(ClientSubnet {
+            source_prefix,
+            scope_prefix,
+            address,
+        })
+    }
+
+    //按照prefix掩码之后的网段, 比如 1.2.3.0/24
+    pub fn to_key(&self, prefix: u8) -> String {
+        match self.address {
+            IpAddr::V4(ip) => {
+                let octets = mask(&ip.octets(), prefix);
+                format!("{}/{}", Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]), prefix)
+            }
+            IpAddr::V6(ip) => {
+                let octets = mask(&ip.octets(), prefix);
+                let mut array = [0u8; 16];
+                array.copy_from_slice(&octets);
+                format!("{}/{}", Ipv6Addr::from(array), prefix)
+            }
+        }
+    }
+
+    fn family(&self) -> u16 {
+        match self.address {
+            IpAddr::V4(_) => 1,
+            IpAddr::V6(_) => 2,
+        }
+    }
+
+    fn address_bytes(&self) -> Vec<u8> {
+        let len = (self.source_prefix as usize + 7) / 8;
+        match self.address {
+            IpAddr::V4(ip) => mask(&ip.octets(), self.source_prefix)[..len].to_vec(),
+            IpAddr::V6(ip) => mask(&ip.octets(), self.source_prefix)[..len].to_vec(),
+        }
+    }
+}
+
+fn mask(octets: &[u8], prefix: u8) -> Vec<u8> {
+    octets.iter().enumerate().map(|(i, b)| {
+        let bits = (prefix as usize).saturating_sub(i * 8).min(8);
+        if bits == 0 {
+            0
+        } else {
+            b & (0xFFu8 << (8 - bits))
+        }
+    }).collect()
+}
+
+impl From<&ClientSubnet> for Vec<u8> {
+    fn from(subnet: &ClientSubnet) -> Self {
+        let address = subnet.address_bytes();
+        let mut vec = Vec::new();
+        vec.extend(&ECS_CODE.to_be_bytes());
+        vec.extend(&(4 + address.len() as u16).to_be_bytes());
+        vec.extend(&subnet.family().to_be_bytes());
+        vec.push(subnet.source_prefix);
+        vec.push(subnet.scope_prefix);
+        vec.extend(address);
+        vec
+    }
+}
+
+//只带ECS选项的OPT记录
+pub fn wrap_opt(subnet: &ClientSubnet) -> Vec<u8> {
+    let option: Vec<u8> = subnet.into();
+    let mut vec = Vec::new();
+    vec.push(0);
+    vec.extend(&OPT_TYPE.to_be_bytes());
+    vec.extend(&UDP_PAYLOAD_SIZE.to_be_bytes());
+    vec.extend(&0u32.to_be_bytes());
+    vec.extend(&(option.len() as u16).to_be_bytes());
+    vec.extend(option);
+    vec
+}
+
+//解析附加记录, 只关心OPT记录里面的ECS选项, 其他的跳过
+pub fn parse_additional(cursor: &Cursor<u8>, count: u16) -> Option<ClientSubnet> {
+    let mut subnet = None;
+    for _ in 0..count {
+        if cursor.peek() == 0 {
+            cursor.take();
+        } else {
+            unzip_domain(cursor);
+        }
+        let _type = u16::from_be_bytes(cursor.take_bytes());
+        cursor.move_to(6);
+        let data_len = u16::from_be_bytes(cursor.take_bytes()) as usize;
+        if _type != OPT_TYPE {
+            cursor.move_to(data_len);
+            continue;
+        }
+        let end = cursor.get_current_index() + data_len;
+        while cursor.get_current_index() + 4 <= end {
+            let code = u16::from_be_bytes(cursor.take_bytes());
+            let len = u16::from_be_bytes(cursor.take_bytes()) as usize;
+            let data = cursor.take_slice(len);
+            if code == ECS_CODE {
+                subnet = ClientSubnet::from(data);
+            }
+        }
+        cursor.at(end);
+    }
+    subnet
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::edns::{ClientSubnet, wrap_opt, parse_additional};
+    use crate::cursor::Cursor;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_return_masked_key_when_call_to_key_given_v4_subnet() {
+        let subnet = get_subnet();
+
+        let result = subnet.to_key(subnet.source_prefix);
+
+        assert_eq!("1.2.3.0/24", result)
+    }
+
+    #[test]
+    fn should_return_masked_key_when_call_to_key_given_prefix_not_aligned() {
+        let mut subnet = get_subnet();
+        subnet.source_prefix = 20;
+
+        let result = subnet.to_key(subnet.source_prefix);
+
+        assert_eq!("1.2.0.0/20", result)
+    }
+
+    #[test]
+    fn should_return_same_subnet_when_parse_given_wrapped_opt() {
+        let subnet = get_subnet();
+        let cursor = Cursor::form(wrap_opt(&subnet).into());
+
+        let result = parse_additional(&cursor, 1);
+
+        let mut expected = get_subnet();
+        expected.address = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 0));
+        assert_eq!(Some(expected), result)
+    }
+
+    #[test]
+    fn should_return_none_when_parse_given_opt_without_ecs() {
+        let bytes = vec![0u8, 0, 41, 2, 0, 0, 0, 0, 0, 0, 4, 0, 10, 0, 0];
+        let cursor = Cursor::form(bytes.into());
+
+        let result = parse_additional(&cursor, 1);
+
+        assert_eq!(None, result)
+    }
+
+    fn get_subnet() -> ClientSubnet {
+        ClientSubnet {
+            source_prefix: 24,
+            scope_prefix: 0,
+            address: IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)),
+        }
+    }
+}
@@ -3,14 +3,17 @@ mod question;
 mod answer;
 mod basic;
 mod query;
+mod edns;
 
 use crate::cursor::Cursor;
 
 const C_FACTOR: u8 = 192u8;
 const DC_FACTOR: u16 = 16383u16;
 
-pub use answer::{DnsAnswer, Ipv4Answer, FailureAnswer, SoaAnswer};
+pub use answer::{Answer, DnsAnswer, Ipv4Answer, FailureAnswer, SoaAnswer, LocalAnswer, LocalRecord,
+                 RawAnswer, MAX_CNAME_DEPTH};
 pub use query::DnsQuery;
+pub use edns::ClientSubnet;
 
 fn parse_name(cursor: &Cursor<u8>, name_vec: &mut Vec<u8>) {
     if cursor.peek() & C_FACTOR == C_FACTOR {
@@ -32,11 +35,18 @@ fn parse_name(cursor: &Cursor<u8>, name_vec: &mut Vec<u8>) {
 fn unzip_domain(cursor: &Cursor<u8>) -> String {
     let mut domain_vec = Vec::new();
     parse_name(cursor, &mut domain_vec);
-    domain_vec.remove(0);
+    //根域名没有内容
+    if !domain_vec.is_empty() {
+        domain_vec.remove(0);
+    }
     String::from_utf8(domain_vec).unwrap()
 }
 
 fn wrap_name(name: &String) -> Vec<u8> {
+    //根域名
+    if name.is_empty() || name == "." {
+        return vec![0];
+    }
     let split = name.split('.');
     let mut vec = Vec::new();
     for s in split {
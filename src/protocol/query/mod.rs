@@ -1,7 +1,9 @@
 use crate::protocol::basic::BasicData;
 use crate::protocol::{basic};
+use crate::protocol::edns::{ClientSubnet, parse_additional, wrap_opt};
 use crate::system::{QueryBuf, next_id};
 use crate::cursor::Cursor;
+use std::net::IpAddr;
 
 const QUERY_ONLY_RECURSIVELY: u16 = 0x0100;
 const QUERY_RECURSIVELY_AD: u16 = 0x0120;
@@ -9,6 +11,9 @@ const QUERY_RECURSIVELY_AD: u16 = 0x0120;
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct DnsQuery {
     basic: BasicData,
+    client_subnet: Option<ClientSubnet>,
+    //发出查询的客户端地址, 不会发给上游
+    client: Option<IpAddr>,
 }
 
 impl DnsQuery {
@@ -21,38 +26,115 @@ impl DnsQuery {
     pub fn get_name(&self) -> &String {
         self.basic.get_name()
     }
+    pub fn get_type(&self) -> u16 {
+        self.basic.get_type()
+    }
+
+    pub fn get_client_subnet(&self) -> Option<&ClientSubnet> {
+        self.client_subnet.as_ref()
+    }
+
+    pub fn get_client(&self) -> Option<&IpAddr> {
+        self.client.as_ref()
+    }
+
+    pub fn set_client(&mut self, client: IpAddr) {
+        self.client = Some(client);
+    }
 
     pub fn is_supported(&self) -> bool {
         let flags = self.basic.get_flags();
         flags == QUERY_ONLY_RECURSIVELY || flags == QUERY_RECURSIVELY_AD
     }
+
+    //查询指定类型的记录
+    pub fn with_type(domain: &str, _type: u16) -> Self {
+        let basic = basic::Builder::new()
+            .id(next_id())
+            .name(domain.to_string())
+            ._type(_type)
+            .flags(QUERY_ONLY_RECURSIVELY)
+            .build();
+        DnsQuery {
+            basic,
+            client_subnet: None,
+            client: None,
+        }
+    }
+
+    //换一个域名查询, 类型, ECS和客户端不变, 改写和安全搜索查询目标域名用
+    pub fn redirect(&self, domain: &str) -> Self {
+        let mut query = DnsQuery::with_type(domain, self.get_type());
+        query.client_subnet = self.client_subnet.clone();
+        query.client = self.client;
+        query
+    }
 }
 
 impl From<QueryBuf> for DnsQuery {
     fn from(buf: QueryBuf) -> Self {
+        let additional_count = u16::from_be_bytes([buf[10], buf[11]]);
         let cursor = Cursor::form(buf.into());
+        let basic = BasicData::from(&cursor);
+        let client_subnet = parse_additional(&cursor, additional_count);
         DnsQuery {
-            basic: BasicData::from(&cursor)
+            basic,
+            client_subnet,
+            client: None,
         }
     }
 }
 
 impl From<&str> for DnsQuery {
     fn from(domain: &str) -> Self {
-        let basic = basic::Builder::new()
-            .id(next_id())
-            .name(domain.to_string())
-            .flags(QUERY_ONLY_RECURSIVELY)
-            .build();
-        DnsQuery {
-            basic
-        }
+        DnsQuery::with_type(domain, 1)
     }
 }
 
 impl From<&DnsQuery> for Vec<u8> {
     fn from(query: &DnsQuery) -> Self {
         let data = &query.basic;
-        data.into()
+        let mut vec: Vec<u8> = data.into();
+        //客户端带了ECS就转发给上游
+        if let Some(subnet) = &query.client_subnet {
+            vec[10..12].copy_from_slice(&1u16.to_be_bytes());
+            vec.extend(wrap_opt(subnet));
+        }
+        vec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::DnsQuery;
+    use crate::system::{QueryBuf, default_value};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_keep_client_subnet_when_to_bytes_given_query_with_ecs() {
+        let bytes: Vec<u8> = vec![0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 1,
+                                  3, 119, 119, 119, 5, 98, 97, 105, 100, 117, 3, 99, 111, 109, 0, 0, 1, 0, 1,
+                                  0, 0, 41, 16, 0, 0, 0, 0, 0, 0, 11, 0, 8, 0, 7, 0, 1, 24, 0, 1, 2, 3];
+        let mut buf: QueryBuf = default_value();
+        buf[..bytes.len()].copy_from_slice(&bytes);
+
+        let query = DnsQuery::from(buf);
+        let result: Vec<u8> = (&query).into();
+
+        let subnet = query.get_client_subnet().unwrap();
+        assert_eq!(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 0)), subnet.address);
+        assert_eq!(24, subnet.source_prefix);
+        assert_eq!(bytes[..34], result[..34]);
+        assert_eq!(bytes[36..], result[36..]);
+    }
+
+    #[test]
+    fn should_not_have_additional_when_to_bytes_given_query_without_ecs() {
+        let query = DnsQuery::from("www.baidu.com");
+
+        let result: Vec<u8> = (&query).into();
+
+        assert_eq!(None, query.get_client_subnet());
+        assert_eq!([0, 0], result[10..12]);
     }
 }
\ No newline at end of file
@@ -0,0 +1,244 @@
+use crate::config::Config;
+use crate::system::{Result, FileNotFoundError};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Certificate, Client, Response, StatusCode};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, BufReader};
+
+pub type ResourceReader = Box<dyn AsyncBufRead + Unpin + Send>;
+
+//http的条件请求用的, 上次返回的ETag和Last-Modified
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+//读取文件或者url, url会校验证书, 有超时和大小限制, 支持gzip
+#[derive(Clone)]
+pub struct ResourceClient {
+    client: Client,
+    max_bytes: usize,
+}
+
+impl Default for ResourceClient {
+    fn default() -> Self {
+        ResourceClient {
+            client: Client::builder().timeout(Duration::from_secs(60)).build().unwrap(),
+            max_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+impl ResourceClient {
+    pub async fn from(config: &Config) -> Result<Self> {
+        let timeout = Duration::from_secs(config.resource_timeout_s as u64);
+        let mut builder = Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout);
+        //除了内置的根证书, 还可以加一个自己的CA证书, pem格式
+        if !config.resource_ca_file.is_empty() {
+            let pem = tokio::fs::read(&config.resource_ca_file).await.map_err(|e| {
+                FileNotFoundError {
+                    path: config.resource_ca_file.clone(),
+                    supper: Box::new(e),
+                }
+            })?;
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+        Ok(ResourceClient {
+            client: builder.build()?,
+            max_bytes: config.resource_max_bytes,
+        })
+    }
+
+    //值可以是文件路径或者是url路径
+    pub async fn open(&self, path: &str) -> Result<ResourceReader> {
+        let (reader, _) = self.open_if_modified(path, &Validators::default()).await?
+            .ok_or("resource is not modified")?;
+        Ok(reader)
+    }
+
+    //url没有变化(304)的话返回None, 文件每次都会重新读
+    pub async fn open_if_modified(&self, path: &str, validators: &Validators)
+                                  -> Result<Option<(ResourceReader, Validators)>> {
+        if path.starts_with("http") {
+            self.open_url(path, validators).await
+        } else {
+            Ok(Some((open_file(path).await?, Validators::default())))
+        }
+    }
+
+    async fn open_url(&self, url: &str, validators: &Validators)
+                      -> Result<Option<(ResourceReader, Validators)>> {
+        let mut request = self.client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+        let mut response = request.send().await?;
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(format!("http status {} from {}", status, url).into());
+        }
+        let new_validators = Validators {
+            etag: get_header(&response, ETAG),
+            last_modified: get_header(&response, LAST_MODIFIED),
+        };
+        //整个body读到内存里面, 超过大小限制就放弃
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > self.max_bytes {
+                return Err(format!("{} is larger than {} bytes", url, self.max_bytes).into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(Some((Box::new(std::io::Cursor::new(body)), new_validators)))
+    }
+}
+
+fn get_header(response: &Response, name: reqwest::header::HeaderName) -> Option<String> {
+    response.headers().get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| String::from(v))
+}
+
+async fn open_file(file_path: &str) -> Result<ResourceReader> {
+    let file = File::open(file_path).await.map_err(|e| {
+        FileNotFoundError {
+            path: String::from(file_path),
+            supper: Box::new(e),
+        }
+    })?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resource::{ResourceClient, Validators};
+    use crate::system::Result;
+    use reqwest::Client;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    //gzip压缩之后的 "address /a.com/#\n"
+    const GZIP_BODY: [u8; 37] = [31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 75, 76, 73, 41, 74, 45, 46, 86,
+        208, 79, 212, 75, 206, 207, 213, 87, 230, 2, 0, 113, 116, 162, 148, 17, 0, 0, 0];
+
+    #[tokio::test]
+    async fn should_return_body_and_validators_when_open_given_ok_response() -> Result<()> {
+        let (url, _) = serve_once(response("200 OK", "ETag: \"abc\"\r\n", b"address /a.com/#\n")).await;
+
+        let (reader, validators) = ResourceClient::default()
+            .open_if_modified(&url, &Validators::default()).await?.unwrap();
+
+        assert_eq!(b"address /a.com/#\n".to_vec(), read_all(reader).await);
+        assert_eq!(Some("\"abc\"".into()), validators.etag);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_return_none_and_send_validators_when_open_given_not_modified() -> Result<()> {
+        let (url, request) = serve_once(response("304 Not Modified", "", b"")).await;
+        let validators = Validators {
+            etag: Some("\"abc\"".into()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+        };
+
+        let result = ResourceClient::default().open_if_modified(&url, &validators).await?;
+
+        let request = request.await.unwrap().to_lowercase();
+        assert!(result.is_none());
+        assert!(request.contains("if-none-match: \"abc\""));
+        assert!(request.contains("if-modified-since: wed, 21 oct 2015 07:28:00 gmt"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_return_error_when_open_given_not_found_response() {
+        let (url, _) = serve_once(response("404 Not Found", "", b"")).await;
+
+        let result = ResourceClient::default().open(&url).await;
+
+        assert!(result.is_err())
+    }
+
+    #[tokio::test]
+    async fn should_return_error_when_open_given_body_larger_than_limit() {
+        let (url, _) = serve_once(response("200 OK", "", &[b'a'; 64])).await;
+        let mut client = ResourceClient::default();
+        client.max_bytes = 32;
+
+        let result = client.open(&url).await;
+
+        assert!(result.is_err())
+    }
+
+    #[tokio::test]
+    async fn should_return_decoded_body_when_open_given_gzip_response() -> Result<()> {
+        let (url, _) = serve_once(response("200 OK", "Content-Encoding: gzip\r\n", &GZIP_BODY)).await;
+
+        let reader = ResourceClient::default().open(&url).await?;
+
+        assert_eq!(b"address /a.com/#\n".to_vec(), read_all(reader).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_return_error_when_open_given_server_not_respond() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            let _socket = listener.accept().await;
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+        let mut client = ResourceClient::default();
+        client.client = Client::builder().timeout(Duration::from_millis(100)).build().unwrap();
+
+        let result = client.open(&url).await;
+
+        assert!(result.is_err())
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut vec = format!("HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                              status, headers, body.len()).into_bytes();
+        vec.extend_from_slice(body);
+        vec
+    }
+
+    //本地的http服务, 只处理一个请求, 返回收到的请求头
+    async fn serve_once(response: Vec<u8>) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/filter.txt", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let len = socket.read(&mut buf).await.unwrap();
+                if len == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..len]);
+            }
+            socket.write_all(&response).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    async fn read_all(mut reader: crate::resource::ResourceReader) -> Vec<u8> {
+        let mut vec = Vec::new();
+        reader.read_to_end(&mut vec).await.unwrap();
+        vec
+    }
+}
@@ -0,0 +1,212 @@
+mod parser;
+
+use crate::protocol::{DnsQuery, LocalAnswer, LocalRecord, MAX_CNAME_DEPTH};
+use crate::system::Result;
+use parser::{parse_zone, ParsedRecord};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+//本地的权威区域, 从zone文件加载
+pub struct Zone {
+    origin: String,
+    soa: LocalRecord,
+    //否定应答的ttl, soa的ttl和minimum里面小的那个
+    negative_ttl: u32,
+    records: HashMap<String, Vec<ParsedRecord>>,
+}
+
+impl Zone {
+    //origin为空的话用soa记录的域名
+    pub fn parse(text: &str, origin: &str) -> Result<Self> {
+        let parsed = parse_zone(text, origin);
+        let soa = parsed.iter().find(|r| r.record._type == 6)
+            .map(|r| r.record.clone())
+            .ok_or("zone文件里面没有SOA记录")?;
+        let origin = soa.name.clone();
+        let minimum = u32::from_be_bytes(soa.data[soa.data.len() - 4..].try_into().unwrap());
+        let mut records: HashMap<String, Vec<ParsedRecord>> = HashMap::new();
+        let suffix = format!(".{}", origin);
+        parsed.into_iter().for_each(|r| {
+            if r.record.name == origin || r.record.name.ends_with(&suffix) {
+                records.entry(r.record.name.clone()).or_default().push(r);
+            } else {
+                warn!("{} 不在区域 {} 里面, 忽略", r.record.name, origin);
+            }
+        });
+        Ok(Zone {
+            origin,
+            negative_ttl: soa.ttl.min(minimum),
+            soa,
+            records,
+        })
+    }
+
+    pub fn get_origin(&self) -> &String {
+        &self.origin
+    }
+
+    pub fn contains(&self, name: &String) -> bool {
+        name == &self.origin || name.ends_with(&format!(".{}", self.origin))
+    }
+
+    //有这个域名的子域名, 这个域名就存在, 只是没有记录
+    fn has_descendant(&self, name: &String) -> bool {
+        let suffix = format!(".{}", name);
+        self.records.keys().any(|k| k.ends_with(&suffix))
+    }
+
+    //先精确匹配, 再从最近的上级域名开始找通配符, 碰到存在的上级域名就停止
+    fn find(&self, name: &String) -> Option<Vec<LocalRecord>> {
+        if let Some(records) = self.records.get(name) {
+            return Some(records.iter().map(|r| r.record.clone()).collect());
+        }
+        if self.has_descendant(name) {
+            return Some(vec![]);
+        }
+        let mut ancestor = name.as_str();
+        while let Some(parent) = ancestor.splitn(2, '.').nth(1) {
+            if !self.contains(&parent.to_string()) {
+                break;
+            }
+            if let Some(records) = self.records.get(&format!("*.{}", parent)) {
+                return Some(records.iter().map(|r| {
+                    let mut record = r.record.clone();
+                    record.name = name.clone();
+                    record
+                }).collect());
+            }
+            if self.records.contains_key(parent) {
+                break;
+            }
+            ancestor = parent;
+        }
+        None
+    }
+
+    fn cname_target(&self, name: &String) -> Option<&String> {
+        self.records.get(name)
+            .or_else(|| {
+                //通配符的cname
+                let parent = name.splitn(2, '.').nth(1)?;
+                self.records.get(&format!("*.{}", parent))
+            })
+            .and_then(|records| records.iter().find_map(|r| r.target.as_ref()))
+    }
+
+    //域名不存在返回NXDOMAIN, 没有这个类型的记录返回NODATA, 都带上soa记录
+    pub fn answer(&self, query: &DnsQuery) -> LocalAnswer {
+        let _type = query.get_type();
+        let mut name = query.get_name().to_lowercase();
+        let mut answers = Vec::new();
+        let mut rcode = 0;
+        let mut with_soa = false;
+        for _ in 0..MAX_CNAME_DEPTH {
+            let records = match self.find(&name) {
+                Some(records) => records,
+                None => {
+                    rcode = 3;
+                    with_soa = true;
+                    break;
+                }
+            };
+            //255是ANY
+            let matched: Vec<LocalRecord> = records.iter()
+                .filter(|r| r._type == _type || _type == 255).cloned().collect();
+            if !matched.is_empty() {
+                answers.extend(matched);
+                break;
+            }
+            match records.iter().find(|r| r._type == 5) {
+                Some(cname) => {
+                    answers.push(cname.clone());
+                    match self.cname_target(&name) {
+                        //指向区域外面的域名由客户端自己去查
+                        Some(target) if self.contains(target) => name = target.clone(),
+                        _ => break,
+                    }
+                }
+                None => {
+                    with_soa = true;
+                    break;
+                }
+            }
+        }
+        let mut answer = LocalAnswer::new(query, rcode);
+        answer.set_authoritative();
+        answers.into_iter().for_each(|r| answer.add_answer(r));
+        if with_soa {
+            let mut soa = self.soa.clone();
+            soa.ttl = self.negative_ttl;
+            answer.add_authority(soa);
+        }
+        answer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::zone::Zone;
+    use crate::protocol::DnsQuery;
+
+    const ZONE: &str = "$TTL 1h\n\
+        @ IN SOA ns1 hostmaster ( 1 1h 15m 1w 5m )\n\
+        \tIN NS ns1\n\
+        ns1 IN A 192.168.1.1\n\
+        nas IN A 192.168.1.2\n\
+        www IN CNAME nas\n\
+        ext IN CNAME example.com.\n\
+        a.b IN A 192.168.1.3\n\
+        *.dev IN A 192.168.1.4\n";
+
+    #[test]
+    fn should_return_zone_when_parse_given_default_origin() {
+        let zone = Zone::parse(ZONE, "home.arpa").unwrap();
+
+        assert_eq!("home.arpa", zone.get_origin());
+        assert_eq!(300, zone.negative_ttl);
+        assert!(Zone::parse("nas IN A 192.168.1.2\n", "home.arpa").is_err());
+    }
+
+    #[test]
+    fn should_return_records_when_answer_given_existing_name() {
+        let zone = Zone::parse(ZONE, "home.arpa").unwrap();
+
+        let answer = zone.answer(&DnsQuery::with_type("NAS.home.arpa", 1));
+
+        assert_eq!((0, 1), (answer.get_rcode(), answer.get_answers().len()));
+    }
+
+    #[test]
+    fn should_follow_cname_in_zone_when_answer_given_cname() {
+        let zone = Zone::parse(ZONE, "home.arpa").unwrap();
+
+        let inside = zone.answer(&DnsQuery::with_type("www.home.arpa", 1));
+        let outside = zone.answer(&DnsQuery::with_type("ext.home.arpa", 1));
+
+        assert_eq!(vec![5, 1], inside.get_answers().iter().map(|r| r._type).collect::<Vec<u16>>());
+        assert_eq!(vec![5], outside.get_answers().iter().map(|r| r._type).collect::<Vec<u16>>());
+    }
+
+    #[test]
+    fn should_return_nxdomain_or_nodata_when_answer_given_missing_record() {
+        let zone = Zone::parse(ZONE, "home.arpa").unwrap();
+
+        let nxdomain = zone.answer(&DnsQuery::with_type("printer.home.arpa", 1));
+        let nodata = zone.answer(&DnsQuery::with_type("nas.home.arpa", 28));
+        let empty_non_terminal = zone.answer(&DnsQuery::with_type("b.home.arpa", 1));
+
+        assert_eq!((3, 0), (nxdomain.get_rcode(), nxdomain.get_answers().len()));
+        assert_eq!((0, 0), (nodata.get_rcode(), nodata.get_answers().len()));
+        assert_eq!((0, 0), (empty_non_terminal.get_rcode(), empty_non_terminal.get_answers().len()));
+    }
+
+    #[test]
+    fn should_match_wildcard_when_answer_given_name_under_wildcard() {
+        let zone = Zone::parse(ZONE, "home.arpa").unwrap();
+
+        let answer = zone.answer(&DnsQuery::with_type("api.dev.home.arpa", 1));
+
+        assert_eq!(1, answer.get_answers().len());
+        assert_eq!("api.dev.home.arpa", answer.get_answers()[0].name);
+    }
+}
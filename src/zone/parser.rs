@@ -0,0 +1,324 @@
+use crate::protocol::LocalRecord;
+use crate::system::Result;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    //带引号的字符串, TXT记录用
+    quoted: bool,
+}
+
+//一条记录, 括号里面的多行算一条
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    line: usize,
+    //行首是空白的话, owner和上一条记录一样
+    blank_owner: bool,
+    tokens: Vec<Token>,
+}
+
+fn tokenize(text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut token = String::new();
+    let mut in_quote = false;
+    let mut depth = 0;
+    let mut line = 1;
+    let mut entry_line = 1;
+    let mut blank_owner = false;
+    let mut line_start = true;
+    let mut chars = text.chars().peekable();
+    let flush = |token: &mut String, tokens: &mut Vec<Token>| {
+        if !token.is_empty() {
+            tokens.push(Token { text: std::mem::take(token), quoted: false });
+        }
+    };
+    while let Some(c) = chars.next() {
+        if line_start && depth == 0 && tokens.is_empty() {
+            blank_owner = c == ' ' || c == '\t';
+            entry_line = line;
+        }
+        line_start = false;
+        if in_quote {
+            match c {
+                '\\' => if let Some(next) = chars.next() { token.push(next) },
+                '"' => {
+                    in_quote = false;
+                    tokens.push(Token { text: std::mem::take(&mut token), quoted: true });
+                }
+                '\n' => {
+                    line += 1;
+                    token.push(c);
+                }
+                _ => token.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                flush(&mut token, &mut tokens);
+                in_quote = true;
+            }
+            //注释到行尾
+            ';' => while chars.peek().map(|n| *n != '\n').unwrap_or(false) {
+                chars.next();
+            },
+            '(' => {
+                flush(&mut token, &mut tokens);
+                depth += 1;
+            }
+            ')' => {
+                flush(&mut token, &mut tokens);
+                depth = (depth - 1).max(0);
+            }
+            '\n' => {
+                flush(&mut token, &mut tokens);
+                line += 1;
+                line_start = true;
+                if depth == 0 && !tokens.is_empty() {
+                    entries.push(Entry { line: entry_line, blank_owner, tokens: std::mem::take(&mut tokens) });
+                }
+            }
+            ' ' | '\t' | '\r' => flush(&mut token, &mut tokens),
+            _ => token.push(c),
+        }
+    }
+    flush(&mut token, &mut tokens);
+    if !tokens.is_empty() {
+        entries.push(Entry { line: entry_line, blank_owner, tokens });
+    }
+    entries
+}
+
+//@是origin, 点结尾的是绝对域名, 其他的加上origin, 都转成小写并且去掉结尾的点
+pub fn absolute_name(name: &str, origin: &str) -> String {
+    let name = name.to_lowercase();
+    if name == "@" {
+        origin.into()
+    } else if let Some(name) = name.strip_suffix('.') {
+        name.into()
+    } else if origin.is_empty() {
+        name
+    } else {
+        format!("{}.{}", name, origin)
+    }
+}
+
+//支持 3600, 1h, 1d2h 这种写法
+fn parse_ttl(value: &str) -> Option<u32> {
+    if let Ok(ttl) = value.parse::<u32>() {
+        return Some(ttl);
+    }
+    let mut total: u32 = 0;
+    let mut number = String::new();
+    for c in value.to_lowercase().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u32>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if number.is_empty() { Some(total) } else { None }
+}
+
+fn record_type(value: &str) -> Option<u16> {
+    match value.to_uppercase().as_str() {
+        "A" => Some(1),
+        "NS" => Some(2),
+        "CNAME" => Some(5),
+        "SOA" => Some(6),
+        "PTR" => Some(12),
+        "MX" => Some(15),
+        "TXT" => Some(16),
+        "AAAA" => Some(28),
+        "SRV" => Some(33),
+        _ => None,
+    }
+}
+
+//解析出来的一条记录, target是cname指向的域名
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRecord {
+    pub record: LocalRecord,
+    pub target: Option<String>,
+}
+
+fn number<T: std::str::FromStr>(tokens: &[Token], index: usize) -> Result<T> {
+    tokens.get(index).and_then(|t| t.text.parse::<T>().ok())
+        .ok_or_else(|| format!("第{}个数据不是数字", index + 1).into())
+}
+
+fn name(tokens: &[Token], index: usize, origin: &str) -> Result<String> {
+    tokens.get(index).map(|t| absolute_name(&t.text, origin))
+        .ok_or_else(|| format!("缺少第{}个数据", index + 1).into())
+}
+
+fn parse_rdata(owner: String, _type: u16, ttl: u32, rdata: &[Token], origin: &str) -> Result<ParsedRecord> {
+    let mut target = None;
+    let record = match _type {
+        1 => LocalRecord::ip(owner, ttl, &name(rdata, 0, "")?.parse::<Ipv4Addr>()?.into()),
+        28 => LocalRecord::ip(owner, ttl, &name(rdata, 0, "")?.parse::<Ipv6Addr>()?.into()),
+        2 | 5 | 12 => {
+            let value = name(rdata, 0, origin)?;
+            if _type == 5 {
+                target = Some(value.clone());
+            }
+            LocalRecord::with_name(owner, _type, ttl, &value)
+        }
+        15 => LocalRecord::mx(owner, ttl, number(rdata, 0)?, &name(rdata, 1, origin)?),
+        33 => LocalRecord::srv(owner, ttl, number(rdata, 0)?, number(rdata, 1)?, number(rdata, 2)?,
+                               &name(rdata, 3, origin)?),
+        6 => {
+            let mut values = [0u32; 5];
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = rdata.get(i + 2).and_then(|t| parse_ttl(&t.text))
+                    .ok_or("SOA的数字格式不对")?;
+            }
+            LocalRecord::soa_with(owner, ttl, &name(rdata, 0, origin)?, &name(rdata, 1, origin)?, values)
+        }
+        _ => {
+            let texts: Vec<String> = rdata.iter().map(|t| t.text.clone()).collect();
+            if texts.is_empty() {
+                return Err("TXT记录没有内容".into());
+            }
+            LocalRecord::txt(owner, ttl, &texts)
+        }
+    };
+    Ok(ParsedRecord { record, target })
+}
+
+//解析zone文件, 格式错误的记录会被忽略并输出日志, origin是默认的origin, 可以被$ORIGIN覆盖
+pub fn parse_zone(text: &str, origin: &str) -> Vec<ParsedRecord> {
+    let mut origin = absolute_name(origin, "");
+    let mut default_ttl: Option<u32> = None;
+    let mut last_ttl: Option<u32> = None;
+    let mut last_owner: Option<String> = None;
+    let mut records = Vec::new();
+    for entry in tokenize(text) {
+        let tokens = &entry.tokens;
+        let first = &tokens[0];
+        if first.text.starts_with('$') && !first.quoted {
+            match (first.text.to_uppercase().as_str(), tokens.get(1)) {
+                ("$ORIGIN", Some(value)) => origin = absolute_name(&value.text, &origin),
+                ("$TTL", Some(value)) => default_ttl = parse_ttl(&value.text),
+                _ => error!("zone文件第{}行: 不支持的指令 {}", entry.line, first.text),
+            }
+            continue;
+        }
+        let (owner, mut index) = if entry.blank_owner {
+            (last_owner.clone(), 0)
+        } else {
+            (Some(absolute_name(&first.text, &origin)), 1)
+        };
+        let owner = match owner {
+            Some(owner) => owner,
+            None => {
+                error!("zone文件第{}行: 没有域名", entry.line);
+                continue;
+            }
+        };
+        last_owner = Some(owner.clone());
+        //类型前面可以有ttl和class, 顺序不固定
+        let mut ttl = None;
+        let mut class_ok = true;
+        while let Some(token) = tokens.get(index) {
+            match token.text.to_uppercase().as_str() {
+                "IN" => {}
+                "CH" | "HS" | "CS" => class_ok = false,
+                other => match parse_ttl(other) {
+                    Some(value) => ttl = Some(value),
+                    None => break,
+                }
+            }
+            index += 1;
+        }
+        let _type = match tokens.get(index).and_then(|t| record_type(&t.text)) {
+            Some(_type) if class_ok => _type,
+            _ => {
+                error!("zone文件第{}行: 不支持的记录 {:?}", entry.line,
+                       tokens.iter().map(|t| t.text.as_str()).collect::<Vec<&str>>());
+                continue;
+            }
+        };
+        if ttl.is_some() {
+            last_ttl = ttl;
+        }
+        let ttl = ttl.or(default_ttl).or(last_ttl).unwrap_or(3600);
+        match parse_rdata(owner, _type, ttl, &tokens[index + 1..], &origin) {
+            Ok(record) => records.push(record),
+            Err(e) => error!("zone文件第{}行: {}", entry.line, e),
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::zone::parser::{tokenize, parse_ttl, parse_zone, absolute_name};
+    use crate::protocol::LocalRecord;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_join_lines_when_tokenize_given_parentheses_quotes_and_comments() {
+        let entries = tokenize("@ IN SOA ns hostmaster ( ; comment\n 1 2 3\n 4 5 )\n  TXT \"a ; b\" \"c\\\"d\"\n");
+
+        assert_eq!(2, entries.len());
+        assert_eq!(10, entries[0].tokens.len());
+        assert!(!entries[0].blank_owner);
+        assert!(entries[1].blank_owner);
+        assert_eq!(4, entries[1].line);
+        assert_eq!(vec!["TXT", "a ; b", "c\"d"],
+                   entries[1].tokens.iter().map(|t| t.text.as_str()).collect::<Vec<&str>>());
+    }
+
+    #[test]
+    fn should_return_seconds_when_parse_ttl_given_units() {
+        assert_eq!(Some(3600), parse_ttl("3600"));
+        assert_eq!(Some(3600), parse_ttl("1h"));
+        assert_eq!(Some(93600), parse_ttl("1d2h"));
+        assert_eq!(None, parse_ttl("IN"));
+        assert_eq!(None, parse_ttl("A"));
+    }
+
+    #[test]
+    fn should_return_absolute_name_when_absolute_name_given_relative_names() {
+        assert_eq!("home.arpa", absolute_name("@", "home.arpa"));
+        assert_eq!("nas.home.arpa", absolute_name("NAS", "home.arpa"));
+        assert_eq!("example.com", absolute_name("example.com.", "home.arpa"));
+    }
+
+    #[test]
+    fn should_return_records_when_parse_zone_given_zone_file() {
+        let text = "$ORIGIN home.arpa.\n$TTL 1h\n\
+            @ IN SOA ns1 hostmaster (\n 2021080201 ; serial\n 1h 15m 1w 5m )\n\
+            \tIN NS ns1\n\
+            ns1 IN A 192.168.1.1\n\
+            nas 600 IN A 192.168.1.2\n\
+            \tIN AAAA fd00::2\n\
+            www IN CNAME nas\n\
+            @ IN MX 10 mail.example.com.\n\
+            _http._tcp IN SRV 0 5 80 nas\n\
+            bad IN A 999.1.1.1\n\
+            x IN HINFO a b\n";
+
+        let records = parse_zone(text, "");
+
+        assert_eq!(vec![6, 2, 1, 1, 28, 5, 15, 33],
+                   records.iter().map(|r| r.record._type).collect::<Vec<u16>>());
+        assert_eq!(LocalRecord::ip("nas.home.arpa".into(), 600, &IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))),
+                   records[3].record);
+        assert_eq!(("nas.home.arpa".to_string(), 3600), (records[4].record.name.clone(), records[4].record.ttl));
+        assert_eq!(Some("nas.home.arpa".to_string()), records[5].target);
+        assert_eq!("_http._tcp.home.arpa", records[7].record.name);
+    }
+}